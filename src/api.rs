@@ -1,24 +1,103 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use reqwest_cookie_store::CookieStoreMutex;
 
-use crate::constants::DEFAULT_HEADERS;
+use crate::{constants::DEFAULT_HEADERS, error::FreeCarnivalError, shared::models::HttpVersion};
 
 pub(crate) mod auth;
 pub(crate) mod product;
+pub(crate) mod release;
+
+/// Headers servers and CDNs use to identify a request, in order of preference
+const REQUEST_ID_HEADERS: [&str; 4] = ["x-request-id", "x-amz-request-id", "x-amz-cf-id", "cf-ray"];
+
+/// Turns an error status of `res` into `FreeCarnivalError::Http`, keeping the status, the URL
+/// without its query and the server's request id (if any) so they can be reported.
+pub(crate) fn check_status(res: reqwest::Response) -> Result<reqwest::Response, FreeCarnivalError> {
+    let status = res.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(res);
+    }
+
+    let mut url = res.url().clone();
+    url.set_query(None);
+    let request_id = REQUEST_ID_HEADERS.iter().find_map(|name| {
+        res.headers()
+            .get(*name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    });
+    Err(FreeCarnivalError::Http {
+        status,
+        url: url.to_string(),
+        request_id,
+    })
+}
+
+/// Environment variables a proxy is read from when `--proxy` isn't given, in order of preference
+const PROXY_ENV_VARS: [&str; 4] = ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"];
+
+/// The proxy to send requests through: `url`, or the first proxy environment variable that is
+/// set. Hosts in `NO_PROXY` bypass it. Unlike reqwest, which ignores proxy variables it can't
+/// parse, an invalid URL is an error so requests don't silently skip the proxy.
+pub(crate) fn proxy_from(url: Option<&str>) -> Result<Option<reqwest::Proxy>, FreeCarnivalError> {
+    let url = match url {
+        Some(url) => url.to_owned(),
+        None => match PROXY_ENV_VARS
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        {
+            Some(url) => url,
+            None => return Ok(None),
+        },
+    };
+
+    match reqwest::Proxy::all(&url) {
+        Ok(proxy) => Ok(Some(proxy.no_proxy(reqwest::NoProxy::from_env()))),
+        // reqwest only says "builder error", the reason is at the end of the source chain
+        Err(err) => {
+            let mut reason: &dyn std::error::Error = &err;
+            while let Some(source) = reason.source() {
+                reason = source;
+            }
+            Err(FreeCarnivalError::InvalidProxy(url, reason.to_string()))
+        }
+    }
+}
 
 pub(crate) trait GalaClient {
-    fn with_gala(cookie_store: &Arc<CookieStoreMutex>) -> Self;
+    fn with_gala(
+        cookie_store: &Arc<CookieStoreMutex>,
+        http_version: HttpVersion,
+        proxy: Option<reqwest::Proxy>,
+        timeout: Option<Duration>,
+    ) -> Self;
 }
 
 impl GalaClient for reqwest::Client {
-    fn with_gala(cookie_store: &Arc<CookieStoreMutex>) -> Self {
-        reqwest::Client::builder()
+    fn with_gala(
+        cookie_store: &Arc<CookieStoreMutex>,
+        http_version: HttpVersion,
+        proxy: Option<reqwest::Proxy>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        let mut builder = reqwest::Client::builder()
             .default_headers(DEFAULT_HEADERS.to_owned())
             .cookie_provider(cookie_store.clone())
             .user_agent("galaClient")
-            .use_rustls_tls()
-            .build()
-            .unwrap()
+            .use_rustls_tls();
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        let builder = match http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+
+        builder.build().unwrap()
     }
 }
@@ -1,20 +1,35 @@
+use std::collections::HashSet;
+
+use reqwest::{header, StatusCode};
+
 use crate::{
-    config::{LibraryConfig, UserConfig},
+    api::check_status,
+    config::{GalaConfig, LibraryConfig, SessionConfig, UserConfig},
     constants::BASE_URL,
+    credentials,
+    error::FreeCarnivalError,
+    output::status,
     shared::models::api::{LoginResult, SyncResult, UserInfo, UserInfoShowcaseContent},
 };
 
+/// Logs in with `username` and `password`. Accounts with the email security code enabled are
+/// asked for the code first, which is sent back as `code`.
 pub(crate) async fn login(
     client: &reqwest::Client,
-    username: &String,
-    password: &String,
+    username: &str,
+    password: &str,
+    code: Option<&str>,
 ) -> Result<Option<LoginResult>, reqwest::Error> {
-    let params = [("usre", username), ("usrp", password)];
+    let mut params = vec![("usre", username), ("usrp", password)];
+    if let Some(code) = code {
+        params.push(("usrc", code));
+    }
     let res = client
         .post(format!("{}/login_new/gcl", *BASE_URL))
         .form(&params)
         .send()
         .await?;
+    log::debug!("POST {} -> {}", res.url(), res.status());
     let body = res.text().await?;
 
     match serde_json::from_str::<LoginResult>(&body) {
@@ -23,42 +38,184 @@ pub(crate) async fn login(
     }
 }
 
-pub(crate) async fn sync(client: &reqwest::Client) -> Result<Option<SyncResult>, reqwest::Error> {
-    let res = client
-        .get(format!("{}/login_new/user_info", *BASE_URL))
-        .send()
-        .await?;
+/// Syncs the user info and library. Fails with `FreeCarnivalError::Auth` when the session
+/// cookies are missing or expired, and with `FreeCarnivalError::InvalidUserInfo` when the
+/// response can't be read.
+///
+/// Unless `force` is set, the server is asked to only send the library if it changed since the
+/// last sync. Forcing it makes the server check the session again, e.g. to refresh it.
+pub(crate) async fn sync(
+    client: &reqwest::Client,
+    force: bool,
+) -> Result<SyncResult, FreeCarnivalError> {
+    let library_config = LibraryConfig::load().unwrap_or_default();
+    let mut req = client.get(format!("{}/login_new/user_info", *BASE_URL));
+    if !force {
+        if let Some(etag) = &library_config.etag {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &library_config.last_modified {
+            req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let res = req.send().await?;
+    log::debug!("GET {} -> {}", res.url(), res.status());
 
+    if res.status() == StatusCode::NOT_MODIFIED {
+        let user_config = UserConfig::load().unwrap_or_default();
+        if user_config.user_info.is_some() {
+            return Ok(SyncResult {
+                library_config,
+                user_config,
+            });
+        }
+    }
+
+    if matches!(
+        res.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        return Err(FreeCarnivalError::Auth);
+    }
+    let res = check_status(res)?;
+
+    let get_header = |name: header::HeaderName| {
+        res.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let etag = get_header(header::ETAG);
+    let last_modified = get_header(header::LAST_MODIFIED);
     let body = res.text().await?;
 
     match serde_json::from_str::<UserInfo>(&body) {
         Ok(user_info) => {
-            if user_info.status != "success" || user_info.user_found != "true" {
-                return Ok(None);
+            if user_info.user_found != "true" {
+                return Err(FreeCarnivalError::Auth);
+            }
+            if user_info.status != "success" {
+                return Err(FreeCarnivalError::InvalidUserInfo(format!(
+                    "status is {:?}",
+                    user_info.status
+                )));
             }
-            let user_collection = match serde_json::from_str::<UserInfoShowcaseContent>(&body) {
+            let mut user_collection = match serde_json::from_str::<UserInfoShowcaseContent>(&body) {
                 Ok(user_info) => match user_info.showcase_content {
                     Some(showcase) => showcase.content.user_collection,
                     None => vec![],
                 },
                 Err(err) => {
-                    println!("Failed to parse user library: {err:?}");
+                    log::warn!("Failed to parse user library: {err:?}");
                     vec![]
                 }
             };
+            // The same product is sometimes listed more than once, only the first one is kept
+            let mut seen_ids = HashSet::new();
+            user_collection.retain(|product| seen_ids.insert(product.id));
 
-            Ok(Some(SyncResult {
+            Ok(SyncResult {
                 library_config: LibraryConfig {
                     collection: user_collection,
+                    etag,
+                    last_modified,
                 },
                 user_config: UserConfig {
                     user_info: Some(user_info),
                 },
-            }))
+            })
+        }
+        Err(err) => Err(FreeCarnivalError::InvalidUserInfo(err.to_string())),
+    }
+}
+
+/// Syncs like `sync`, but when the session has expired and an account was saved with
+/// `login --remember`, logs in again with its password from the OS keyring and retries once.
+pub(crate) async fn sync_relogging_in(
+    client: &reqwest::Client,
+    force: bool,
+) -> Result<SyncResult, FreeCarnivalError> {
+    match sync(client, force).await {
+        Err(FreeCarnivalError::Auth) => {
+            let Some(email) = SessionConfig::load()
+                .ok()
+                .and_then(|session| session.remembered_email)
+            else {
+                return Err(FreeCarnivalError::Auth);
+            };
+            let password = match credentials::load_password(&email).await {
+                Ok(password) => password,
+                Err(err) => {
+                    log::warn!("Failed to read password from keyring: {err}");
+                    return Err(FreeCarnivalError::Auth);
+                }
+            };
+
+            status!("Session expired, logging in again as {email}...");
+            match login(client, &email, &password, None).await? {
+                Some(login) if login.is_success() => sync(client, force).await,
+                // Accounts with the email security code can't log in without someone to enter it
+                _ => Err(FreeCarnivalError::Auth),
+            }
         }
-        Err(_) => {
-            println!("Failed to sync data. Are you logged in?");
-            Ok(None)
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    #[tokio::test]
+    async fn only_rejected_sessions_are_auth_errors() {
+        let client = reqwest::Client::new();
+        // Started before `sync` loads the library, so it's loaded from the test config dir
+        let _ = test_server::url();
+        let responses: [(u16, &str); 4] = [
+            (401, ""),
+            (200, r#"{"status": "success", "user_found": "false"}"#),
+            (200, "<html>Maintenance</html>"),
+            (200, r#"{"status": "error", "user_found": "true"}"#),
+        ];
+        let mut results = vec![];
+        for (status, body) in responses {
+            test_server::route("/login_new/user_info", move || {
+                (status, body.as_bytes().to_vec())
+            });
+            results.push(sync(&client, false).await);
         }
+
+        assert!(matches!(results[0], Err(FreeCarnivalError::Auth)));
+        assert!(matches!(results[1], Err(FreeCarnivalError::Auth)));
+        assert!(matches!(
+            results[2],
+            Err(FreeCarnivalError::InvalidUserInfo(_))
+        ));
+        assert!(matches!(
+            results[3],
+            Err(FreeCarnivalError::InvalidUserInfo(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_code_requests_need_a_security_code() {
+        let client = reqwest::Client::new();
+        let _ = test_server::url();
+        let responses = [
+            r#"{"status": "success", "message": "Welcome back!"}"#,
+            r#"{"status": "code_required", "message": "We sent a security code to your email"}"#,
+            r#"{"status": "error", "message": "Wrong password. Forgot it? Get a reset code."}"#,
+        ];
+        let mut results = vec![];
+        for body in responses {
+            test_server::route("/login_new/gcl", move || (200, body.as_bytes().to_vec()));
+            let login = login(&client, "user@example.com", "password", None).await;
+            results.push(login.unwrap().unwrap());
+        }
+
+        assert!(results[0].is_success() && !results[0].needs_security_code());
+        assert!(!results[1].is_success() && results[1].needs_security_code());
+        assert!(!results[2].is_success() && !results[2].needs_security_code());
     }
 }
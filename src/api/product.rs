@@ -1,7 +1,13 @@
-use bytes::Bytes;
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use tokio::time::timeout;
 
 use crate::{
+    api::check_status,
     constants::{CONTENT_URL, DEV_URL},
+    error::FreeCarnivalError,
+    helpers::RateLimiter,
     shared::models::api::{BuildOs, GameDetails, GameDetailsResponse, Product, ProductVersion},
 };
 
@@ -9,7 +15,7 @@ pub(crate) async fn get_build_manifest(
     client: &reqwest::Client,
     product: &Product,
     build_version: &ProductVersion,
-) -> Result<Bytes, reqwest::Error> {
+) -> Result<Bytes, FreeCarnivalError> {
     let res = client
         .get(format!(
             "{}/DevShowCaseSourceVolume/dev_fold_{}/{}/{}/{}_manifest.csv",
@@ -21,7 +27,8 @@ pub(crate) async fn get_build_manifest(
         ))
         .send()
         .await?;
-    let body = res.bytes().await?;
+    log::debug!("GET {} -> {}", res.url(), res.status());
+    let body = check_status(res)?.bytes().await?;
     Ok(body)
 }
 
@@ -29,7 +36,7 @@ pub(crate) async fn get_build_manifest_chunks(
     client: &reqwest::Client,
     product: &Product,
     build_version: &ProductVersion,
-) -> Result<Bytes, reqwest::Error> {
+) -> Result<Bytes, FreeCarnivalError> {
     let res = client
         .get(format!(
             "{}/DevShowCaseSourceVolume/dev_fold_{}/{}/{}/{}_manifest_chunks.csv",
@@ -41,22 +48,45 @@ pub(crate) async fn get_build_manifest_chunks(
         ))
         .send()
         .await?;
-    let body = res.bytes().await?;
+    log::debug!("GET {} -> {}", res.url(), res.status());
+    let body = check_status(res)?.bytes().await?;
     Ok(body)
 }
 
+/// Downloads a chunk, giving up with `ChunkStalled` if no data arrives for `stall_timeout`.
+/// With `rate_limit`, reading the response waits for the limiter, and the request isn't bound
+/// by the client's total timeout since a throttled chunk can take longer than that on purpose.
 pub(crate) async fn download_chunk(
     client: &reqwest::Client,
     product: &Product,
     os: &BuildOs,
     chunk_sha: &String,
-) -> Result<Bytes, reqwest::Error> {
-    let res = client
-        .get(get_chunk_url(product, os, chunk_sha))
-        .send()
-        .await?;
-    let bytes = res.bytes().await?;
-    Ok(bytes)
+    stall_timeout: Duration,
+    rate_limit: Option<&RateLimiter>,
+) -> Result<Bytes, FreeCarnivalError> {
+    let stalled = |_| FreeCarnivalError::ChunkStalled(chunk_sha.to_owned(), stall_timeout);
+    let url = get_chunk_url(product, os, chunk_sha);
+    log::debug!("GET {url}");
+    let mut req = client.get(url);
+    if rate_limit.is_some() {
+        req = req.timeout(Duration::MAX);
+    }
+    let res = timeout(stall_timeout, req.send())
+        .await
+        .map_err(stalled)??;
+    let mut res = check_status(res)?;
+
+    let mut bytes = BytesMut::new();
+    while let Some(part) = timeout(stall_timeout, res.chunk())
+        .await
+        .map_err(stalled)??
+    {
+        if let Some(rate_limit) = rate_limit {
+            rate_limit.take(part.len()).await;
+        }
+        bytes.extend_from_slice(&part);
+    }
+    Ok(bytes.freeze())
 }
 
 pub(crate) async fn get_game_details(
@@ -72,19 +102,20 @@ pub(crate) async fn get_game_details(
         .query(query)
         .send()
         .await?;
+    log::debug!("GET {} -> {}", res.url(), res.status());
 
     let body = res.text().await?;
     match serde_json::from_str::<GameDetailsResponse>(&body) {
         Ok(data) => {
             if data.status != "success" {
-                println!("Server failed to deliver game details");
+                log::warn!("Server failed to deliver game details: {}", data.message);
                 return Ok(None);
             }
 
             Ok(Some(data.product_data))
         }
         Err(_) => {
-            println!(
+            log::warn!(
                 "Failed to get game details for {}. Are you logged in?",
                 product.name
             );
@@ -0,0 +1,30 @@
+use bytes::Bytes;
+
+use crate::{constants::RELEASES_URL, shared::models::api::Release};
+
+/// Fetches the latest published release of this program.
+pub(crate) async fn get_latest_release(
+    client: &reqwest::Client,
+) -> Result<Release, reqwest::Error> {
+    client
+        .get(*RELEASES_URL)
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Release>()
+        .await
+}
+
+pub(crate) async fn download_asset(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<Bytes, reqwest::Error> {
+    client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await
+}
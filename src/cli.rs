@@ -2,7 +2,10 @@ use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 
-use crate::{constants::*, shared::models::api::BuildOs};
+use crate::{
+    constants::*,
+    shared::models::{api::BuildOs, HttpVersion},
+};
 
 /// Native cross-platform indieGala client
 #[derive(Parser, Debug)]
@@ -15,20 +18,105 @@ use crate::{constants::*, shared::models::api::BuildOs};
 pub(crate) struct Cli {
     #[command(subcommand)]
     pub(crate) command: Commands,
+    /// Only print errors and the output a command was asked for. Progress and status messages
+    /// are suppressed.
+    #[arg(long, short, global = true)]
+    pub(crate) quiet: bool,
+    /// Assume yes to every confirmation prompt.
+    #[arg(long, short, global = true)]
+    pub(crate) yes: bool,
+    /// Print machine-readable JSON instead of text, for commands that support it.
+    #[arg(long, global = true)]
+    pub(crate) json: bool,
+    /// Disable colored output. Color is also disabled when the NO_COLOR environment variable is
+    /// set, or when output isn't a terminal.
+    #[arg(long, global = true)]
+    pub(crate) no_color: bool,
+    /// Don't sync the library before running the command, using the last synced library instead.
+    /// With login, only the session is saved and the library is synced by a later command.
+    #[arg(long, global = true)]
+    pub(crate) no_sync: bool,
+    /// Force the HTTP version used for requests. Defaults to the `http_version` setting, which
+    /// negotiates it with the server unless changed.
+    #[arg(long, global = true, value_name = "VERSION")]
+    pub(crate) http_version: Option<HttpVersion>,
+    /// Send every request through this proxy, e.g. http://proxy:3128 or socks5://proxy:1080.
+    /// Defaults to the HTTPS_PROXY or ALL_PROXY environment variable. Hosts listed in NO_PROXY
+    /// are always reached directly.
+    #[arg(long, global = true, value_name = "URL")]
+    pub(crate) proxy: Option<String>,
+    /// How many seconds a request can take in total, including downloading the response, before
+    /// it fails. Chunk downloads that time out are retried. 0 disables the timeout.
+    #[arg(long, global = true, value_name = "SECONDS", default_value_t = *DEFAULT_REQUEST_TIMEOUT)]
+    pub(crate) timeout: u64,
+    /// Directory to read and write config files in, instead of the CARNIVAL_CONFIG_PATH
+    /// environment variable or the default config directory.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) config_dir: Option<PathBuf>,
+    /// Directory to write generated files to (download stats and verify reports given as a
+    /// relative path), instead of the default config directory.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) output_dir: Option<PathBuf>,
+    /// Append a JSON heartbeat line to this file while installing, updating or verifying, so a
+    /// supervisor can tell a stuck process from a slow one. Use /dev/fd/N to write to an
+    /// inherited file descriptor.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) heartbeat: Option<PathBuf>,
+    /// Log more details to stderr: -v logs requests and chunk progress, -vv logs everything,
+    /// including HTTP internals. Only warnings and errors are logged by default. RUST_LOG
+    /// overrides this when set.
+    ///
+    /// Goes before the command (e.g. `-v install`), since some commands use -v for a version.
+    #[arg(long, short, action = clap::ArgAction::Count)]
+    pub(crate) verbose: u8,
+    /// Seconds between heartbeat lines.
+    #[arg(long, global = true, value_name = "SECONDS", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) heartbeat_interval: u64,
 }
 
 impl Cli {
-    /// Checks if a sync is needed before handling command
-    pub(crate) fn needs_sync(&self) -> bool {
-        !matches!(
-            &self.command,
-            Commands::Login {
-                email: _,
-                password: _,
-            } | Commands::Logout
-                | Commands::Uninstall { slug: _, keep: _ }
-                | Commands::Verify { slug: _ }
-        )
+    /// Checks if a sync is needed before handling command. Commands listed in `skip_sync` (by
+    /// their CLI name) don't sync.
+    pub(crate) fn needs_sync(&self, skip_sync: &[String]) -> bool {
+        if self.no_sync {
+            return false;
+        }
+
+        let name = match &self.command {
+            Commands::Library { .. } => "library",
+            Commands::Search { .. } => "search",
+            Commands::Install { .. } => "install",
+            Commands::Complete { .. } => "complete",
+            Commands::ListUpdates { .. } => "list-updates",
+            Commands::Update { .. } => "update",
+            Commands::Repair { .. } => "repair",
+            Commands::Launch { .. } => "launch",
+            Commands::Info { .. } => "info",
+            Commands::Versions { .. } => "versions",
+            Commands::Fetch { .. } => "fetch",
+            Commands::Orphans { .. } => "orphans",
+            Commands::Import { .. } => "import",
+            Commands::Login { .. }
+            | Commands::LoginToken { .. }
+            | Commands::Logout { .. }
+            | Commands::Uninstall { .. }
+            | Commands::Verify { .. }
+            | Commands::Tag { .. }
+            | Commands::Untag { .. }
+            | Commands::Relink { .. }
+            | Commands::Move { .. }
+            | Commands::Export { .. }
+            | Commands::Config { .. }
+            | Commands::ListInstalled { .. }
+            | Commands::SelfUpdate { .. }
+            | Commands::Completions { .. }
+            | Commands::Refresh
+            | Commands::Stats => return false,
+            #[cfg(not(target_os = "windows"))]
+            Commands::Runners => return false,
+        };
+
+        !skip_sync.iter().any(|command| command == name)
     }
 }
 
@@ -36,19 +124,70 @@ impl Cli {
 pub(crate) enum Commands {
     /// Authenticate with your indieGala account
     Login {
-        /// Your indieGala account email
-        email: String,
-        /// Your indieGala password, can be left blank for interactive login
+        /// Your indieGala account email. Read from GALA_EMAIL when not given.
+        email: Option<String>,
+        /// Your indieGala password. Read from GALA_PASSWORD when not given, or asked for if
+        /// that isn't set either.
         password: Option<String>,
+        /// Read the password from the first line of stdin, for scripts
+        #[arg(long, conflicts_with = "password")]
+        password_stdin: bool,
+        /// Save your password in the OS keyring after logging in
+        #[arg(long)]
+        save_to_keyring: bool,
+        /// Use the password saved with --save-to-keyring instead of asking for it
+        #[arg(long, conflicts_with_all = ["password", "password_stdin", "save_to_keyring"])]
+        from_keyring: bool,
+        /// Save your password in the OS keyring and use it to log in again when the session
+        /// expires
+        #[arg(long)]
+        remember: bool,
+        /// The security code indieGala emails when logging in to accounts that have it enabled.
+        /// Read from GALA_2FA_CODE when not given, or asked for when needed.
+        #[arg(long)]
+        code: Option<String>,
+    },
+    /// Authenticate with the session cookie of a browser that is logged in to indieGala, for when
+    /// `login` is blocked by a captcha
+    LoginToken {
+        /// The cookie, copied from the browser's developer tools, e.g. "name=value; other=value"
+        cookie: String,
     },
     /// Logout from your indieGala account
-    Logout,
+    Logout {
+        /// Only clear the saved session cookies, keeping your user info and library
+        #[arg(long)]
+        reset_session: bool,
+    },
     /// List your library
-    Library,
-    /// Install a game from your library
+    Library {
+        /// Print each game using this template instead, e.g. "{slug}: {name}". The {slug},
+        /// {name}, {id} and {namespace} placeholders are filled in.
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
+    },
+    /// Find games in your library whose name, slug or developer namespace contains a query
+    ///
+    /// Only synced games are searched. indieGala has no store search endpoint for clients, so
+    /// games you don't own can't be looked up.
+    Search {
+        /// Text to look for, case-insensitively
+        query: String,
+        /// Only show games that are installed
+        #[arg(long)]
+        installed: bool,
+        /// Only show games that are owned but not installed
+        #[arg(long, conflicts_with = "installed")]
+        owned_only: bool,
+    },
+    /// Discard the cached library and download it again, keeping your session and installed
+    /// games
+    Refresh,
+    /// Install games from your library
     Install {
-        /// The slug of the game e.g. syberia-ii
-        slug: String,
+        /// The slugs of the games e.g. syberia-ii. Games are installed one after another.
+        #[arg(required = true)]
+        slugs: Vec<String>,
         /// Install specific build version. If ommited, the latest build version will be installed.
         #[arg(long, short)]
         version: Option<String>,
@@ -60,32 +199,124 @@ pub(crate) enum Commands {
         /// creating additional subdirectories.
         #[arg(long)]
         path: Option<PathBuf>,
+        /// Name of the subdirectory the game is installed in, inside the base install path.
+        /// Defaults to the game's slugged name.
+        #[arg(long, value_name = "NAME", conflicts_with = "path")]
+        dir_name: Option<String>,
         /// The build target OS to install
         #[arg(long)]
         os: Option<BuildOs>,
+        /// Write the build manifest to this file and exit without downloading anything. The
+        /// manifest is written as JSON if the file ends in .json, or as CSV otherwise. The chunks
+        /// manifest and the build are saved next to it as <OUT>.chunks.csv and <OUT>.build.json.
+        #[arg(long, value_name = "OUT", conflicts_with_all = ["path", "base_path", "dir_name"])]
+        manifest_only: Option<PathBuf>,
+        /// Install from a build manifest written by --manifest-only, along with the files saved
+        /// next to it, instead of fetching it. Chunks are still downloaded. Installs the build
+        /// the manifest was written for, which must match --version if it's given.
+        #[arg(long, value_name = "FILE", conflicts_with = "manifest_only")]
+        manifest: Option<PathBuf>,
+        /// Directory where in-progress downloads are written to before being moved to the
+        /// install path. Defaults to the `staging_dir` setting, or a directory next to the
+        /// install path.
+        #[arg(long)]
+        staging_dir: Option<PathBuf>,
+        /// Only install files, in manifest order, until their total size reaches this many bytes.
+        /// The remaining files can be installed later with the `complete` command.
+        #[arg(long, value_name = "BYTES", conflicts_with = "manifest_only")]
+        max_size: Option<u64>,
+        /// Install even if the build has files that only differ in case and the install path is
+        /// on a case-insensitive filesystem. The last of those files in the manifest is kept.
+        #[arg(long)]
+        force: bool,
+        /// Continue an install that was interrupted (e.g. by a crash or a reboot), keeping the
+        /// chunks that were already downloaded to the staging directory.
+        #[arg(long)]
+        resume: bool,
+        /// Don't check that the staging and install paths have enough free space for the build
+        /// before downloading it, for filesystems that report their free space wrong. The first
+        /// of the `base_paths` setting is used without checking it either.
+        #[arg(long)]
+        no_space_check: bool,
+        /// Only download the build to this directory, along with its manifest, without
+        /// installing it. Install it later with --from-local.
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["manifest_only", "staging_dir"])]
+        download_only: Option<PathBuf>,
+        /// Install a build downloaded with --download-only from this directory. Its files are
+        /// checked against the manifest saved with them, then copied to the install path. The
+        /// directory is left as it was, delete it once the game is installed.
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["version", "os", "manifest_only", "manifest", "download_only", "staging_dir", "max_size", "resume"]
+        )]
+        from_local: Option<PathBuf>,
+        #[command(flatten)]
+        install_opts: InstallOpts,
+    },
+    /// Install the files left out by `install --max-size`
+    Complete {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
         #[command(flatten)]
         install_opts: InstallOpts,
     },
     /// Uninstalls a game
     Uninstall {
         /// The slug of the game e.g. syberia-ii
-        slug: String,
+        #[arg(required_unless_present = "tag")]
+        slug: Option<String>,
+        /// Uninstall every game with this tag instead of a single slug.
+        #[arg(long, conflicts_with = "slug")]
+        tag: Option<String>,
         /// Remove game from installed config but do not delete install folder.
         #[arg(long)]
         keep: bool,
+        /// Delete the install folder, but keep files matching the protected paths in
+        /// settings.yml (saves, settings, ...).
+        #[arg(long, conflicts_with = "keep")]
+        keep_saves: bool,
+        /// List the files and directories that would be deleted, and whether saves would be
+        /// kept, without deleting anything.
+        #[arg(long, conflicts_with = "keep")]
+        dry_run: bool,
     },
     /// Lists available updates for installed games.
-    ListUpdates,
+    ListUpdates {
+        /// Only list (and send `update-available` events for) updates that weren't already
+        /// listed by a previous run with this flag, e.g. for scheduled checks.
+        #[arg(long)]
+        new_only: bool,
+    },
     /// Update (or downgrade) an installed game.
     Update {
         /// The slug of the game e.g. syberia-ii
-        slug: String,
+        #[arg(required_unless_present_any = ["all", "tag"])]
+        slug: Option<String>,
+        /// Update every installed game to its latest version.
+        #[arg(long, conflicts_with_all = ["slug", "version", "tag"])]
+        all: bool,
+        /// Update every installed game with this tag to its latest version.
+        #[arg(long, conflicts_with_all = ["slug", "version"])]
+        tag: Option<String>,
         /// Change to a specific version. Don't set this if you just want to update to the latest
         /// version.
         ///
         /// You can get a list of available versions by using the `info` command.
         #[arg(long, short)]
         version: Option<String>,
+        /// Download the build again even if it's the one already installed. Protected files
+        /// (saves, settings, ...) are kept.
+        #[arg(long)]
+        force: bool,
+        /// Verify the updated files once the update is done, downloading the ones that don't
+        /// match again (up to 3 times).
+        #[arg(long)]
+        verify_repair: bool,
+        /// Keep the files the update replaces or removes until it's done, putting them back if
+        /// it fails so the game stays at its current version.
+        #[arg(long)]
+        backup: bool,
         #[command(flatten)]
         install_opts: InstallOpts,
     },
@@ -101,41 +332,297 @@ pub(crate) enum Commands {
         #[cfg(not(target_os = "windows"))]
         #[arg(long)]
         wine_prefix: Option<PathBuf>,
-        /// The WINE bin to use for launching the game
+        /// The WINE bin to use for launching the game. Windows games use the `wine` found in
+        /// PATH when neither this nor --runner is set.
         #[cfg(not(target_os = "windows"))]
         #[arg(long)]
         wine: Option<PathBuf>,
+        /// Name of a WINE or Proton build to launch the game with, as listed by `runners`
+        #[cfg(not(target_os = "windows"))]
+        #[arg(long, conflicts_with = "wine")]
+        runner: Option<String>,
         /// Use a wrapper to launch
         #[arg(long)]
         wrapper: Option<PathBuf>,
+        /// Run the game inside gamescope
+        #[cfg(target_os = "linux")]
+        #[arg(long)]
+        gamescope: bool,
+        /// Arguments passed to gamescope e.g. "-W 1280 -H 800 -r 60". Implies --gamescope.
+        #[cfg(target_os = "linux")]
+        #[arg(long, allow_hyphen_values = true)]
+        gamescope_args: Option<String>,
+        /// GPU to run the game on, for systems with more than one. `nvidia` uses NVIDIA PRIME
+        /// render offload, anything else (e.g. `1` or `pci-0000_03_00_0`) is passed as DRI_PRIME.
+        /// Only supported on Linux.
+        #[arg(long)]
+        gpu: Option<String>,
+        /// Remember the gamescope and GPU settings used for this launch for future launches of
+        /// this game
+        #[cfg(target_os = "linux")]
+        #[arg(long)]
+        remember: bool,
+        /// Comma separated winetricks verbs to install in the WINE prefix before launching
+        /// e.g. vcrun2019,dotnet48. Verbs are only installed once per game.
+        #[cfg(not(target_os = "windows"))]
+        #[arg(long, value_delimiter = ',')]
+        winetricks: Vec<String>,
+        /// Only check that the programs needed to launch the game are installed
+        #[arg(long)]
+        check_deps: bool,
+        /// Print the launch command, working directory and environment as shell variables
+        /// (CARNIVAL_COMMAND, CARNIVAL_CWD and exported variables like WINEPREFIX) instead of
+        /// launching, e.g. for `eval`. Combine with --quiet to only print the variables.
+        #[arg(long, conflicts_with = "check_deps")]
+        print_env: bool,
+        #[command(flatten)]
+        hooks: HookOpts,
+        /// Arguments passed to the game, after `--` (e.g. `launch syberia-ii -- -windowed`)
+        #[arg(last = true, value_name = "GAME_ARGS")]
+        game_args: Vec<String>,
     },
     /// Print info about game
+    ///
+    /// With --json, the product, every build with its size, and the installed build are printed
+    /// as a single JSON object, or as a JSON array of them when more than one slug is given.
     Info {
+        /// The slugs of the games e.g. syberia-ii
+        #[arg(required = true)]
+        slugs: Vec<String>,
+    },
+    /// List every build available for a game
+    ///
+    /// With --json, the builds are printed as a single JSON array.
+    Versions {
         /// The slug of the game e.g. syberia-ii
         slug: String,
+        /// How to sort the builds
+        #[arg(long, value_enum, default_value_t = VersionSort::Date)]
+        sort: VersionSort,
+        /// Also fetch each build's manifest to show its size. This makes a request per build.
+        #[arg(long)]
+        size: bool,
     },
     /// Verify file integrity for an installed game
+    ///
+    /// Exits with a non-zero status if any game fails verification.
     Verify {
+        /// The slug of the game e.g. syberia-ii
+        #[arg(required_unless_present_any = ["all", "tag"])]
+        slug: Option<String>,
+        /// Verify every installed game. Games are verified at the same time, sharing --jobs.
+        #[arg(long, conflicts_with_all = ["slug", "tag"])]
+        all: bool,
+        /// Verify every game with this tag instead of a single slug.
+        #[arg(long, conflicts_with = "slug")]
+        tag: Option<String>,
+        /// Only hash files whose size or modification time changed since the last verification.
+        /// Games that were never verified are fully verified.
+        #[arg(long)]
+        changed: bool,
+        /// Write the files that failed verification to this file, as a JSON list of objects
+        /// with the `slug`, `file_name` and `reason` (`missing`, `size` or `hash`) of each file.
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+        /// How many files are hashed at the same time. Defaults to the number of logical CPUs,
+        /// lower it to verify in the background.
+        #[arg(long, short, default_value_t = *DEFAULT_VERIFY_JOBS)]
+        jobs: usize,
+    },
+    /// Check an installed game chunk by chunk, downloading only the chunks that are corrupted
+    /// and writing them in place
+    Repair {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        #[command(flatten)]
+        install_opts: InstallOpts,
+    },
+    /// Download a single file or directory from a game's build without installing it
+    Fetch {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Path of the file or directory inside the build, as listed in the build manifest
+        path: String,
+        /// Directory to reconstruct the file(s) in. The path inside the build is kept.
+        #[arg(long, default_value = ".")]
+        out: PathBuf,
+        /// Fetch from a specific build version instead of the latest one
+        #[arg(long, short)]
+        version: Option<String>,
+        /// The build target OS to fetch from
+        #[arg(long)]
+        os: Option<BuildOs>,
+        #[command(flatten)]
+        install_opts: InstallOpts,
+    },
+    /// Add tags to an installed game
+    Tag {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Tags to add e.g. vr couch-coop
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from an installed game
+    Untag {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Tags to remove
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// List installed games with their version, install path and size on disk
+    ListInstalled {
+        /// How to sort the games
+        #[arg(long, value_enum, default_value_t = InstalledSort::Name)]
+        sort_by: InstalledSort,
+    },
+    /// List installed games that are no longer in your library
+    Orphans {
+        /// Uninstall the listed games, deleting their install folders
+        #[arg(long)]
+        prune: bool,
+        /// With --prune, only forget the games and keep their install folders
+        #[arg(long, requires = "prune")]
+        keep: bool,
+    },
+    /// Save launch options for an installed game. `launch` uses them when it isn't given the
+    /// matching flag. Without any options, the saved ones are printed.
+    Config {
         /// The slug of the game e.g. syberia-ii
         slug: String,
+        /// The WINE bin to launch the game with
+        #[cfg(not(target_os = "windows"))]
+        #[arg(long)]
+        wine: Option<PathBuf>,
+        /// The WINE prefix to use for this game
+        #[cfg(not(target_os = "windows"))]
+        #[arg(long)]
+        wine_prefix: Option<PathBuf>,
+        /// Wrapper to launch the game with
+        #[arg(long)]
+        wrapper: Option<PathBuf>,
+        /// Argument passed to the game. Can be repeated, and replaces the saved arguments.
+        #[arg(long = "arg", value_name = "ARG", allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// Environment variable set for the game, as NAME=VALUE. Can be repeated.
+        #[arg(long = "env", value_name = "NAME=VALUE", value_parser = parse_env_var)]
+        env: Vec<(String, String)>,
+        /// Command to run before the game is launched. The game won't be launched if it fails.
+        #[arg(long, allow_hyphen_values = true)]
+        pre_launch: Option<String>,
+        /// Command to run after the game exits
+        #[arg(long, allow_hyphen_values = true)]
+        post_exit: Option<String>,
+        /// Forget the saved launch options before saving the given ones
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Move an installed game to another directory, copying it when the directory is on
+    /// another filesystem
+    Move {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Directory to move the game to
+        new_path: PathBuf,
+        /// Move the game even if the directory already exists and isn't empty, overwriting the
+        /// files in it
+        #[arg(long)]
+        force: bool,
+    },
+    /// Point an installed game to the directory it was manually moved to. The files in the new
+    /// directory are checked against the build manifest first.
+    Relink {
+        /// The slug of the game e.g. syberia-ii
+        slug: String,
+        /// Directory the game was moved to
+        new_path: PathBuf,
+    },
+    /// Save the list of installed games to a JSON file, to import it after reinstalling the OS
+    /// or on another machine sharing the same game directories
+    Export {
+        /// File to write the installed games to
+        path: PathBuf,
     },
+    /// Add the installed games of a file written by `export`, so they don't need to be
+    /// downloaded again
+    Import {
+        /// File written by `export`
+        path: PathBuf,
+        /// Only import games whose install directory exists and whose files match their build
+        /// manifest
+        #[arg(long)]
+        verify: bool,
+        /// Replace games that are already installed with the ones in the file
+        #[arg(long)]
+        force: bool,
+    },
+    /// List the WINE and Proton builds found on this system
+    #[cfg(not(target_os = "windows"))]
+    Runners,
+    /// Show how much data was downloaded each month
+    Stats,
+    /// Check if a newer version of FreeCarnival was released
+    SelfUpdate {
+        /// Download the new version for this OS and architecture and replace the running binary
+        /// with it, once it matches the SHA-256 published with the release
+        #[arg(long)]
+        download: bool,
+    },
+    /// Print a shell completion script, e.g. `completions zsh > ~/.zfunc/_freecarnival`
+    Completions {
+        /// The shell to generate the script for
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub(crate) enum VersionSort {
+    /// Newest builds first
+    Date,
+    /// Highest version first
+    Version,
+    /// Grouped by OS, newest builds first
+    Os,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub(crate) enum InstalledSort {
+    /// By slug
+    Name,
+    /// Largest games first, missing ones last
+    Size,
+    /// Highest version first
+    Version,
 }
 
-#[derive(Debug, Args)]
+#[derive(Debug, Clone, Args)]
 pub(crate) struct InstallOpts {
-    /// How many download workers to run at one time.
+    /// How many chunk downloads to run at one time.
     /// Increasing this value will make downloads faster, but use more memory.
     /// Lowering this value will lower memory usage at the cost of slower downloads.
     ///
-    /// Note: Too many download workers can cause unreliable downloads. The default is
+    /// This limit is shared by every game handled in a single run (e.g. `update --all`), so
+    /// the total number of in-flight requests never exceeds it.
+    ///
+    /// Note: Too many concurrent downloads can cause unreliable downloads. The default is
     /// double your CPU_COUNT. You shouldn't deviate too much from this.
-    #[arg(long, default_value_t = *DEFAULT_MAX_DL_WORKERS)]
-    pub(crate) max_download_workers: usize,
+    ///
+    /// Set to `auto` to start with a few downloads and adjust their number while downloading,
+    /// keeping the one that gives the best throughput.
+    #[arg(long, visible_aliases = ["max-download-workers", "max-connections"], default_value_t = Concurrency::Fixed(*DEFAULT_MAX_DL_WORKERS))]
+    pub(crate) max_concurrency: Concurrency,
     /// How much memory to use to store chunks. Lowering this value will potentially make
     /// downloads slower while being lighter on memory usage. Raising it will make the program
     /// use more memory if needed, but can potentially speed up downloads.
     #[arg(long, default_value_t = *DEFAULT_MAX_MEMORY_USAGE)]
     pub(crate) max_memory_usage: usize,
+    /// How many files to write to disk at the same time. This is independent from
+    /// `--max-concurrency`, so network and disk throughput can be tuned separately.
+    ///
+    /// Fast SSDs benefit from a higher value, while spinning disks usually do best with 1 or 2.
+    #[arg(long, default_value_t = *DEFAULT_WRITE_JOBS)]
+    pub(crate) write_jobs: usize,
     /// Print download info instead of installing game.
     #[arg(long, short)]
     pub(crate) info: bool,
@@ -143,6 +630,130 @@ pub(crate) struct InstallOpts {
     /// corrupted/tampered files.
     #[arg(long)]
     pub(crate) skip_verify: bool,
+    /// How many seconds a chunk download can go without receiving any data before it's
+    /// aborted and retried. This catches connections that stay open but stop sending data.
+    #[arg(long, value_name = "SECONDS", default_value_t = *DEFAULT_CHUNK_TIMEOUT)]
+    pub(crate) timeout_per_chunk: u64,
+    /// How many times a chunk download is retried after a transient failure (a connection
+    /// error, a timeout or a 5xx response), waiting twice as long before every attempt. Chunks
+    /// that fail verification are also downloaded again up to this many times.
+    #[arg(long, value_name = "N", default_value_t = *DEFAULT_CHUNK_RETRIES)]
+    pub(crate) retries: usize,
+    /// Limit the combined download speed of every chunk download, in bytes per second. Accepts
+    /// the suffixes k, M and G (e.g. 500k or 2M). Throttled chunk downloads aren't bound by
+    /// `--timeout`, only by `--timeout-per-chunk`.
+    #[arg(long, value_name = "BYTES_PER_SEC", value_parser = parse_rate)]
+    pub(crate) limit_rate: Option<u64>,
+    /// Print how long was spent fetching manifests, downloading, verifying chunks, writing to
+    /// disk and moving files once done.
+    #[arg(long)]
+    pub(crate) profile: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub(crate) struct HookOpts {
+    /// Command to run before the game is launched. The game won't be launched if it fails.
+    #[arg(long, allow_hyphen_values = true)]
+    pub(crate) pre_launch: Option<String>,
+    /// Command to run after the game exits
+    #[arg(long, allow_hyphen_values = true)]
+    pub(crate) post_exit: Option<String>,
+    /// How many seconds a hook can run for before it's killed
+    #[arg(long, default_value_t = 60)]
+    pub(crate) hook_timeout: u64,
+    /// Don't run any hook for this launch, including the ones saved with `config` and the
+    /// `game-exited` event hooks
+    #[arg(long, conflicts_with_all = ["pre_launch", "post_exit"])]
+    pub(crate) no_hooks: bool,
+}
+
+/// How many chunk downloads run at one time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Concurrency {
+    /// Tuned while downloading, based on the measured throughput
+    Auto,
+    Fixed(usize),
+}
+
+impl Concurrency {
+    /// Number of downloads to start with
+    pub(crate) fn initial(&self) -> usize {
+        match self {
+            Self::Auto => *AUTO_DL_WORKERS_START,
+            Self::Fixed(workers) => *workers,
+        }
+    }
+
+    /// Most downloads that can run at one time
+    pub(crate) fn max(&self) -> usize {
+        match self {
+            Self::Auto => *AUTO_DL_WORKERS_MAX,
+            Self::Fixed(workers) => *workers,
+        }
+    }
+}
+
+fn parse_env_var(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+        _ => Err(format!("expected NAME=VALUE, got {value:?}")),
+    }
+}
+
+/// Parses a number of bytes per second, with an optional binary k, M or G suffix.
+fn parse_rate(value: &str) -> Result<u64, String> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&value[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(number) if number >= 1.0 / multiplier as f64 => Ok((number * multiplier as f64) as u64),
+        _ => Err(format!(
+            "expected a positive number of bytes, optionally followed by k, M or G, got {value:?}"
+        )),
+    }
+}
+
+impl std::str::FromStr for Concurrency {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        // No downloads at a time would never download anything
+        match value.parse() {
+            Ok(workers) if workers > 0 => Ok(Self::Fixed(workers)),
+            _ => Err(format!(
+                "expected a positive number or `auto`, got {value:?}"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Concurrency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Fixed(workers) => write!(f, "{workers}"),
+        }
+    }
+}
+
+impl ValueEnum for HttpVersion {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Auto, Self::Http1, Self::Http2]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            Self::Auto => Some(clap::builder::PossibleValue::new("auto")),
+            Self::Http1 => Some(clap::builder::PossibleValue::new("1.1")),
+            Self::Http2 => Some(clap::builder::PossibleValue::new("2")),
+        }
+    }
 }
 
 impl ValueEnum for BuildOs {
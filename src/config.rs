@@ -1,25 +1,87 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use confy::ConfyError;
+use directories::ProjectDirs;
 use reqwest_cookie_store::CookieStore;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use crate::{
     constants::CONFIG_PATH,
     constants::PROJECT_NAME,
+    error::FreeCarnivalError,
+    output,
     shared::models::{
         api::{Product, UserInfo},
-        InstallInfo,
+        HttpVersion, InstallInfo,
     },
 };
 
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+static OUTPUT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Overrides the directory configs are stored in for this run. The config directory is picked
+/// from, in order: `--config-dir`, the `CARNIVAL_CONFIG_PATH` environment variable, and the
+/// OS config directory.
+pub(crate) fn set_config_dir(config_dir: PathBuf) {
+    CONFIG_DIR
+        .set(config_dir)
+        .expect("Config directory was already set");
+}
+
+/// Overrides the directory generated files (download stats, ...) are written to for this run.
+/// Build manifests stay in the config directory, since they're read back by later commands.
+pub(crate) fn set_output_dir(output_dir: PathBuf) {
+    OUTPUT_DIR
+        .set(output_dir)
+        .expect("Output directory was already set");
+}
+
+/// Directory configs are stored in: `--config-dir`, the `CARNIVAL_CONFIG_PATH` environment
+/// variable, or the OS config directory.
+pub(crate) fn config_dir() -> PathBuf {
+    if let Some(config_dir) = CONFIG_DIR.get() {
+        config_dir.to_owned()
+    } else if !CONFIG_PATH.is_empty() {
+        PathBuf::from(&*CONFIG_PATH)
+    } else {
+        ProjectDirs::from("rs", "", *PROJECT_NAME)
+            .unwrap()
+            .config_dir()
+            .to_path_buf()
+    }
+}
+
 pub(crate) trait GalaConfig
 where
     Self: Sized + Serialize + DeserializeOwned + Default,
 {
-    fn load() -> Result<Self, ConfyError> {
-        confy::load_path::<Self>(Self::get_config_path())
+    /// Loads the config. If the file can't be parsed (e.g. after a bad manual edit), the user
+    /// is asked whether to reset it to its default, keeping the bad file next to it as `.bak`.
+    fn load() -> Result<Self, FreeCarnivalError> {
+        let path = Self::get_config_path();
+        match confy::load_path::<Self>(&path) {
+            Err(ConfyError::BadYamlData(err)) => {
+                let backup_path = path.with_extension("yml.bak");
+                eprintln!("{} is corrupted: {err}", path.display());
+                if !output::confirm(&format!(
+                    "Reset it to its default? The corrupted file will be kept at {}.",
+                    backup_path.display()
+                )) {
+                    return Err(FreeCarnivalError::CorruptedConfig { path, backup_path });
+                }
+
+                std::fs::rename(&path, &backup_path)?;
+                let config = Self::default();
+                confy::store_path(&path, &config)?;
+                Ok(config)
+            }
+            result => Ok(result?),
+        }
     }
 
     fn store(&self) -> Result<(), ConfyError> {
@@ -32,8 +94,18 @@ where
 
     fn config_name() -> &'static str;
 
+    /// Whether this file is generated by the program rather than configuring it, so it's stored
+    /// in `--output-dir` when that is set.
+    fn is_generated() -> bool {
+        false
+    }
+
     fn get_config_path() -> PathBuf {
-        if !CONFIG_PATH.is_empty() {
+        if let Some(output_dir) = OUTPUT_DIR.get().filter(|_| Self::is_generated()) {
+            output_dir.join(format!("{}.yml", Self::config_name()))
+        } else if let Some(config_dir) = CONFIG_DIR.get() {
+            config_dir.join(format!("{}.yml", Self::config_name()))
+        } else if !CONFIG_PATH.is_empty() {
             Path::new(&(*CONFIG_PATH))
                 .join(format!("{}.yml", Self::config_name()))
                 .to_path_buf()
@@ -57,6 +129,20 @@ impl GalaConfig for UserConfig {
     }
 }
 
+/// The account logged in with `login --remember`, whose password is saved in the OS keyring
+/// to log in again when the session expires
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub(crate) struct SessionConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) remembered_email: Option<String>,
+}
+
+impl GalaConfig for SessionConfig {
+    fn config_name() -> &'static str {
+        "session"
+    }
+}
+
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub(crate) struct CookieConfig(pub(crate) CookieStore);
 
@@ -69,6 +155,12 @@ impl GalaConfig for CookieConfig {
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub(crate) struct LibraryConfig {
     pub(crate) collection: Vec<Product>,
+    /// `ETag` of the last sync response, used to skip downloading an unchanged library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) etag: Option<String>,
+    /// `Last-Modified` of the last sync response, used when the server doesn't send an `ETag`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_modified: Option<String>,
 }
 
 impl GalaConfig for LibraryConfig {
@@ -84,3 +176,108 @@ impl GalaConfig for InstalledConfig {
         "installed"
     }
 }
+
+/// Bytes downloaded for builds, by month (`YYYY-MM`), shown by the `stats` command
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub(crate) struct UsageConfig {
+    pub(crate) monthly_bytes: BTreeMap<String, u64>,
+}
+
+impl UsageConfig {
+    /// Adds `bytes` to the current month's total.
+    pub(crate) fn record(bytes: u64) -> Result<(), ConfyError> {
+        // Not `load`, whose reset prompt would be drawn under the download's progress bars.
+        // A corrupted file is reported by the caller instead.
+        let mut usage = confy::load_path::<Self>(Self::get_config_path())?;
+        let month = chrono::Local::now().format("%Y-%m").to_string();
+        *usage.monthly_bytes.entry(month).or_default() += bytes;
+        usage.store()
+    }
+}
+
+impl Display for UsageConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.monthly_bytes.is_empty() {
+            return write!(f, "Nothing was downloaded yet");
+        }
+
+        for (month, bytes) in &self.monthly_bytes {
+            writeln!(f, "{month}: {}", human_bytes::human_bytes(*bytes as f64))?;
+        }
+        write!(
+            f,
+            "Total: {}",
+            human_bytes::human_bytes(self.monthly_bytes.values().sum::<u64>() as f64)
+        )
+    }
+}
+
+impl GalaConfig for UsageConfig {
+    fn config_name() -> &'static str {
+        "usage"
+    }
+
+    fn is_generated() -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SettingsConfig {
+    /// Glob patterns, relative to a game's install directory, for files that must never be
+    /// overwritten by an update or deleted by `uninstall --keep-saves` (e.g. saves and settings).
+    #[serde(default = "default_protected_paths")]
+    pub(crate) protected_paths: Vec<String>,
+    /// Directory where in-progress installs are downloaded to before being moved to their
+    /// install path. Installs are staged next to their install path when this isn't set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) staging_dir: Option<PathBuf>,
+    /// Base install paths to pick from, in order of preference, when installing without
+    /// `--path` or `--base-path`. The first one with enough free space for the game is used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) base_paths: Vec<PathBuf>,
+    /// Commands (e.g. `launch`, `info`) that shouldn't sync the library before running.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) skip_sync: Vec<String>,
+    /// HTTP version used for requests: `auto`, `1.1` or `2`. Some proxies and CDNs misbehave
+    /// with one of them.
+    #[serde(default)]
+    pub(crate) http_version: HttpVersion,
+    /// Commands to run when lifecycle events happen, keyed by event name (e.g.
+    /// `install-complete`). See `events::Event` for the events and what hooks receive.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) event_hooks: HashMap<String, Vec<String>>,
+}
+
+impl Default for SettingsConfig {
+    fn default() -> Self {
+        Self {
+            protected_paths: default_protected_paths(),
+            staging_dir: None,
+            base_paths: vec![],
+            skip_sync: vec![],
+            http_version: HttpVersion::default(),
+            event_hooks: HashMap::new(),
+        }
+    }
+}
+
+fn default_protected_paths() -> Vec<String> {
+    [
+        "**/settings.ini",
+        "**/*.sav",
+        "**/save/**",
+        "**/saves/**",
+        "**/savegames/**",
+        "**/savedgames/**",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+impl GalaConfig for SettingsConfig {
+    fn config_name() -> &'static str {
+        "settings"
+    }
+}
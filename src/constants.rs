@@ -5,11 +5,34 @@ use lazy_static::lazy_static;
 use reqwest::header::{self, HeaderMap};
 
 lazy_static! {
-    pub(crate) static ref BASE_URL: &'static str = "https://www.indiegala.com";
-    pub(crate) static ref CONTENT_URL: &'static str = "https://content.indiegalacdn.com";
+    // Tests send every request to a local server instead
+    pub(crate) static ref BASE_URL: &'static str = {
+        #[cfg(test)]
+        return crate::test_server::url();
+        #[cfg(not(test))]
+        return "https://www.indiegala.com";
+    };
+    pub(crate) static ref CONTENT_URL: &'static str = {
+        #[cfg(test)]
+        return crate::test_server::url();
+        #[cfg(not(test))]
+        return "https://content.indiegalacdn.com";
+    };
     pub(crate) static ref DEV_URL: &'static str = "https://developers.indiegala.com";
+    pub(crate) static ref RELEASES_URL: &'static str = "https://api.github.com/repos/Gustash/FreeCarnival/releases/latest";
     pub(crate) static ref MAX_CHUNK_SIZE: usize = 1048576; // 1 MiB
     pub(crate) static ref DEFAULT_MAX_DL_WORKERS: usize = std::cmp::min(num_cpus::get() * 2, 16);
+    pub(crate) static ref AUTO_DL_WORKERS_START: usize = 4;
+    pub(crate) static ref AUTO_DL_WORKERS_MAX: usize = 64;
+    pub(crate) static ref DEFAULT_WRITE_JOBS: usize = std::cmp::min(num_cpus::get(), 4);
+    pub(crate) static ref MAX_SESSION_REFRESHES: usize = 3; // per chunk
+    pub(crate) static ref MAX_CHUNK_STALLS: usize = 5; // per chunk
+    pub(crate) static ref DEFAULT_CHUNK_RETRIES: usize = 5; // per chunk
+    pub(crate) static ref MAX_RETRY_BACKOFF: u64 = 30; // seconds
+    pub(crate) static ref MAX_REPAIR_ATTEMPTS: usize = 3; // per update
+    pub(crate) static ref DEFAULT_CHUNK_TIMEOUT: u64 = 30; // seconds
+    pub(crate) static ref DEFAULT_REQUEST_TIMEOUT: u64 = 300; // seconds
+    pub(crate) static ref DEFAULT_VERIFY_JOBS: usize = std::thread::available_parallelism().map_or(1, |jobs| jobs.get());
     pub(crate) static ref DEFAULT_MAX_MEMORY_USAGE: usize = *MAX_CHUNK_SIZE * 1024; // 1 GiB
     pub(crate) static ref DEFAULT_BASE_INSTALL_PATH: PathBuf = UserDirs::new().expect("Failed to retrieve home directory.").home_dir().join("Games").join(*PROJECT_NAME);
     pub(crate) static ref PROJECT_NAME: &'static str = env!("CARGO_PKG_NAME");
@@ -17,7 +40,7 @@ lazy_static! {
     pub(crate) static ref VERSION_CODENAME: &'static str = include_str!("../CODENAME");
     pub(crate) static ref CONFIG_PATH: String = {
         match std::env::var("CARNIVAL_CONFIG_PATH") {
-            Ok(p) => String::from(p),
+            Ok(p) => p,
             Err(_e) => "".to_string()
         }
     };
@@ -0,0 +1,35 @@
+use keyring::Entry;
+
+use crate::constants::PROJECT_NAME;
+
+// The keyring API is blocking, so it's run outside of the async runtime
+
+/// Saves the password for `email` in the OS keyring.
+pub(crate) async fn save_password(email: &str, password: &str) -> keyring::Result<()> {
+    let email = email.to_owned();
+    let password = password.to_owned();
+    tokio::task::spawn_blocking(move || Entry::new(*PROJECT_NAME, &email)?.set_password(&password))
+        .await
+        .expect("Keyring task panicked")
+}
+
+/// Reads the password saved for `email` from the OS keyring.
+pub(crate) async fn load_password(email: &str) -> keyring::Result<String> {
+    let email = email.to_owned();
+    tokio::task::spawn_blocking(move || Entry::new(*PROJECT_NAME, &email)?.get_password())
+        .await
+        .expect("Keyring task panicked")
+}
+
+/// Removes the password saved for `email` from the OS keyring, if there is one.
+pub(crate) async fn delete_password(email: &str) -> keyring::Result<()> {
+    let email = email.to_owned();
+    tokio::task::spawn_blocking(move || {
+        match Entry::new(*PROJECT_NAME, &email)?.delete_credential() {
+            Err(keyring::Error::NoEntry) => Ok(()),
+            result => result,
+        }
+    })
+    .await
+    .expect("Keyring task panicked")
+}
@@ -0,0 +1,113 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{constants::PROJECT_NAME, shared::models::api::BuildOs};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum FreeCarnivalError {
+    #[error("Request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error(
+        "Request to {url} failed with {status}{}",
+        request_id.as_ref().map(|id| format!(" (request id {id})")).unwrap_or_default()
+    )]
+    Http {
+        status: reqwest::StatusCode,
+        url: String,
+        request_id: Option<String>,
+    },
+    #[error("Your session has expired, please run `{} login` again", *PROJECT_NAME)]
+    Auth,
+    #[error("Couldn't read your account info from indieGala: {0}")]
+    InvalidUserInfo(String),
+    #[error("Invalid proxy URL {0}: {1}")]
+    InvalidProxy(String, String),
+    #[error("Could not find {0} in library")]
+    GameNotFound(String),
+    #[error("Couldn't find a build of {0} to install")]
+    InstallBuild(String),
+    #[error(
+        "{slug} has no {os} build. Builds are available for: {}",
+        if available.is_empty() { "none".to_owned() } else { available.join(", ") }
+    )]
+    NoBuildForOs {
+        slug: String,
+        os: BuildOs,
+        available: Vec<String>,
+    },
+    #[error("{} has no build downloaded with `install --download-only`", .0.display())]
+    NoLocalDownload(PathBuf),
+    #[error("{} has {found} downloaded, not {slug}", path.display())]
+    LocalDownloadMismatch {
+        path: PathBuf,
+        slug: String,
+        found: String,
+    },
+    #[error("{0}")]
+    Verify(String),
+    #[error("{0} is not part of this build")]
+    NotInManifest(String),
+    #[error("Unknown hash algorithm for digest {0}")]
+    UnknownHashAlgorithm(String),
+    #[error(
+        "Can't write to {}: {1}. Make sure you have permission to write there, or pick another location.",
+        .0.display()
+    )]
+    NotWritable(PathBuf, std::io::Error),
+    #[error("No data received for {0}.bin in {}s", .1.as_secs())]
+    ChunkStalled(String, Duration),
+    #[error(
+        "These files only differ in case and would overwrite each other on this filesystem:\n{0}\nUse --force to install anyway, keeping the last file of each pair."
+    )]
+    CaseCollision(String),
+    #[error("{} doesn't match build {1}", .0.display())]
+    ManifestMismatch(PathBuf, String),
+    #[error(
+        "{} is missing the files saved next to it. Write it again with --manifest-only.",
+        .0.display()
+    )]
+    NoManifestBuild(PathBuf),
+    #[error(
+        "Can't resume from {}: {1}. Install again without --resume to start over.",
+        .0.display()
+    )]
+    ResumeState(PathBuf, String),
+    #[error(
+        "Not enough free space in {}: {} needed, {} available. Use --no-space-check to install anyway.",
+        path.display(),
+        human_bytes::human_bytes(*.required as f64),
+        human_bytes::human_bytes(*.available as f64)
+    )]
+    InsufficientSpace {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
+    #[error("{} already exists and isn't empty. Use --force to move into it anyway.", .0.display())]
+    DestinationNotEmpty(PathBuf),
+    #[error("{0}")]
+    Config(#[from] confy::ConfyError),
+    #[error(
+        "{} is corrupted. Fix it, or pass --yes to reset it to its default, keeping it at {}.",
+        path.display(),
+        backup_path.display()
+    )]
+    CorruptedConfig { path: PathBuf, backup_path: PathBuf },
+}
+
+impl FreeCarnivalError {
+    /// Whether the request that failed with this error can succeed if it's sent again, because
+    /// the connection failed or the server had a temporary problem.
+    pub(crate) fn is_transient(&self) -> bool {
+        match self {
+            FreeCarnivalError::Request(err) => {
+                err.is_timeout() || err.is_connect() || err.is_request() || err.is_body()
+            }
+            FreeCarnivalError::Http { status, .. } => {
+                status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
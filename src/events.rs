@@ -0,0 +1,115 @@
+use std::{collections::HashMap, path::Path, process::Stdio, time::Duration};
+
+use serde::Serialize;
+use shlex::split;
+use tokio::{io::AsyncWriteExt, time::timeout};
+
+/// How long an event hook can run for before it's killed
+const EVENT_HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Lifecycle events that run the hooks configured in the `event_hooks` setting.
+///
+/// Hooks receive the event as a single line of JSON on stdin, e.g.
+/// `{"event":"install-complete","slug":"syberia-ii","version":"1.0","install_path":"..."}`, and
+/// the event name and slug in the `FREECARNIVAL_EVENT` and `FREECARNIVAL_SLUG` environment
+/// variables. Fields are only ever added to events, so hooks should ignore fields they don't
+/// know about.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub(crate) enum Event<'a> {
+    InstallComplete {
+        slug: &'a str,
+        version: &'a str,
+        install_path: &'a Path,
+    },
+    UpdateAvailable {
+        slug: &'a str,
+        installed_version: &'a str,
+        version: &'a str,
+    },
+    UpdateComplete {
+        slug: &'a str,
+        from_version: &'a str,
+        to_version: &'a str,
+    },
+    VerifyComplete {
+        slug: &'a str,
+        passed: bool,
+    },
+    GameExited {
+        slug: &'a str,
+        exit_code: Option<i32>,
+    },
+}
+
+impl Event<'_> {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Event::InstallComplete { .. } => "install-complete",
+            Event::UpdateAvailable { .. } => "update-available",
+            Event::UpdateComplete { .. } => "update-complete",
+            Event::VerifyComplete { .. } => "verify-complete",
+            Event::GameExited { .. } => "game-exited",
+        }
+    }
+
+    fn slug(&self) -> &str {
+        match self {
+            Event::InstallComplete { slug, .. }
+            | Event::UpdateAvailable { slug, .. }
+            | Event::UpdateComplete { slug, .. }
+            | Event::VerifyComplete { slug, .. }
+            | Event::GameExited { slug, .. } => slug,
+        }
+    }
+}
+
+/// Runs every hook configured for `event`, one after the other. A failing hook is reported, but
+/// never fails the command that emitted the event.
+pub(crate) async fn emit(event_hooks: &HashMap<String, Vec<String>>, event: Event<'_>) {
+    let hooks = match event_hooks.get(event.name()) {
+        Some(hooks) if !hooks.is_empty() => hooks,
+        _ => return,
+    };
+    let payload = serde_json::to_string(&event).expect("Failed to serialize event");
+
+    for hook in hooks {
+        if let Err(err) = run_event_hook(hook, &event, &payload).await {
+            log::warn!("{} hook `{hook}` failed: {err}", event.name());
+        }
+    }
+}
+
+async fn run_event_hook(hook: &str, event: &Event<'_>, payload: &str) -> Result<(), String> {
+    let argv = match split(hook) {
+        Some(argv) if !argv.is_empty() => argv,
+        _ => return Err("couldn't parse command".to_owned()),
+    };
+
+    let mut child = tokio::process::Command::new(&argv[0])
+        .args(&argv[1..])
+        .env("FREECARNIVAL_EVENT", event.name())
+        .env("FREECARNIVAL_SLUG", event.slug())
+        .stdin(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| err.to_string())?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Hooks don't have to read their stdin, so failing to write to it is fine
+        let _ = stdin.write_all(format!("{payload}\n").as_bytes()).await;
+    }
+
+    match timeout(EVENT_HOOK_TIMEOUT, child.wait()).await {
+        Ok(Ok(status)) if status.success() => Ok(()),
+        Ok(Ok(status)) => Err(format!("exited with: {status}")),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => {
+            let _ = child.kill().await;
+            Err(format!(
+                "timed out after {}s and was killed",
+                EVENT_HOOK_TIMEOUT.as_secs()
+            ))
+        }
+    }
+}
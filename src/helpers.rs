@@ -1,29 +1,44 @@
 use std::{
+    cmp,
     collections::{HashMap, HashSet},
-    path::PathBuf,
-    sync::Arc,
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
 use async_recursion::async_recursion;
 use bytes::Bytes;
-use directories::ProjectDirs;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressStyle};
+use md5::Md5;
 use os_path::OsPath;
 use queues::{queue, IsQueue, Queue};
+use reqwest::StatusCode;
+use sha1::Sha1;
 use sha2::{Digest, Sha256};
 use tokio::{
     fs::File,
-    io::AsyncWriteExt,
+    io::{AsyncSeekExt, AsyncWriteExt},
     sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
 };
 
 use crate::{
     api,
-    cli::InstallOpts,
-    constants::{MAX_CHUNK_SIZE, PROJECT_NAME},
+    cli::{Concurrency, InstallOpts},
+    config::{config_dir, UsageConfig},
+    constants::{
+        MAX_CHUNK_SIZE, MAX_CHUNK_STALLS, MAX_RETRY_BACKOFF, MAX_SESSION_REFRESHES, PROJECT_NAME,
+    },
+    error::FreeCarnivalError,
+    output::{heartbeat, progress_bars, status},
     shared::models::{
         api::{BuildOs, Product},
-        BuildManifestChunksRecord, BuildManifestRecord, ChangeTag,
+        BuildManifestChunksRecord, BuildManifestRecord, ChangeTag, DownloadStats, PhaseTimings,
+        SavedManifest,
     },
 };
 
@@ -43,7 +58,7 @@ pub(crate) async fn find_exe_recursive(path: &PathBuf) -> Option<PathBuf> {
 
                 if entry_path.is_file() {
                     // Check if the current path is a file with a .exe extension
-                    println!("Checking file: {}", entry_path.display());
+                    status!("Checking file: {}", entry_path.display());
                     if let (Some(ext), Some(file_name)) =
                         (entry_path.extension(), entry_path.file_name())
                     {
@@ -62,7 +77,7 @@ pub(crate) async fn find_exe_recursive(path: &PathBuf) -> Option<PathBuf> {
             }
         }
         Err(err) => {
-            println!("Failed to iterate over {}: {:?}", path.display(), err);
+            log::warn!("Failed to iterate over {}: {:?}", path.display(), err);
         }
     }
 
@@ -72,7 +87,7 @@ pub(crate) async fn find_exe_recursive(path: &PathBuf) -> Option<PathBuf> {
     }
 
     for dir in subdirs {
-        println!("Checking directory: {}", dir.display());
+        status!("Checking directory: {}", dir.display());
         if let Some(exe_path) = find_exe_recursive(&dir.to_path_buf()).await {
             return Some(exe_path);
         }
@@ -81,6 +96,555 @@ pub(crate) async fn find_exe_recursive(path: &PathBuf) -> Option<PathBuf> {
     None
 }
 
+/// Compares two version strings, comparing runs of digits by their numeric value so that
+/// e.g. `1.10` sorts after `1.9`.
+pub(crate) fn compare_versions(a: &str, b: &str) -> cmp::Ordering {
+    let split = |version: &str| -> Vec<String> {
+        let mut parts: Vec<String> = vec![];
+        for c in version.chars() {
+            match parts.last_mut() {
+                Some(part) if part.chars().all(|p| p.is_ascii_digit()) == c.is_ascii_digit() => {
+                    part.push(c)
+                }
+                _ => parts.push(c.to_string()),
+            }
+        }
+        parts
+    };
+
+    for (a, b) in split(a).iter().zip(split(b).iter()) {
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        };
+        if ordering != cmp::Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    a.len().cmp(&b.len())
+}
+
+/// Looks for an executable called `name` in the directories listed in `PATH`.
+pub(crate) fn find_in_path(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Looks for a program given either as a path or as a name to look up in `PATH`.
+pub(crate) fn find_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    find_in_path(program)
+}
+
+/// A WINE or Proton build that can be used to launch Windows games
+#[cfg(not(target_os = "windows"))]
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct Runner {
+    pub(crate) name: String,
+    /// Where the runner was found, e.g. `lutris`
+    pub(crate) source: &'static str,
+    /// The runner's wine binary
+    pub(crate) path: PathBuf,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl std::fmt::Display for Runner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}] {}", self.name, self.source, self.path.display())
+    }
+}
+
+/// Looks for WINE and Proton builds in `PATH`, Lutris' runners directory and Steam's
+/// compatibility tools directories.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn discover_runners() -> Vec<Runner> {
+    let mut runners = vec![];
+    for name in ["wine", "wine64"] {
+        if let Some(path) = find_in_path(name) {
+            runners.push(Runner {
+                name: name.to_owned(),
+                source: "system",
+                path,
+            });
+        }
+    }
+
+    let home = match directories::UserDirs::new() {
+        Some(dirs) => dirs.home_dir().to_path_buf(),
+        None => return runners,
+    };
+    let data_dir = directories::BaseDirs::new()
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| home.join(".local").join("share"));
+    let runner_dirs = [
+        (
+            "lutris",
+            data_dir.join("lutris").join("runners").join("wine"),
+        ),
+        (
+            "steam",
+            home.join(".steam")
+                .join("root")
+                .join("compatibilitytools.d"),
+        ),
+        ("steam", data_dir.join("Steam").join("compatibilitytools.d")),
+    ];
+    for (source, dir) in runner_dirs {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut found: Vec<Runner> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let runner_dir = entry.path();
+                // Lutris runners have bin/wine, Proton builds keep it in files/ or dist/
+                let path = [
+                    runner_dir.join("bin").join("wine"),
+                    runner_dir.join("files").join("bin").join("wine"),
+                    runner_dir.join("dist").join("bin").join("wine"),
+                ]
+                .into_iter()
+                .find(|path| path.is_file())?;
+                Some(Runner {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    source,
+                    path,
+                })
+            })
+            .filter(|runner| !runners.iter().any(|r| r.path == runner.path))
+            .collect();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        runners.extend(found);
+    }
+
+    runners
+}
+
+/// Suggests how to install a missing program on the current platform.
+pub(crate) fn install_hint(program: &str) -> String {
+    let name = Path::new(program)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| program.to_owned());
+
+    if cfg!(target_os = "macos") {
+        format!("Install it with e.g. `brew install {name}`, or check the path is correct.")
+    } else if cfg!(target_os = "windows") {
+        format!("Install {name} and make sure it's in your PATH.")
+    } else {
+        format!(
+            "Install it with your distribution's package manager (e.g. `sudo apt install {name}` \
+            or `sudo pacman -S {name}`), or check the path is correct."
+        )
+    }
+}
+
+/// Reads the PE header of a Windows executable to check if it was built for 32-bit x86.
+#[cfg(not(target_os = "windows"))]
+pub(crate) async fn is_32bit_exe(exe: &Path) -> bool {
+    use tokio::io::AsyncReadExt;
+
+    const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+
+    // The PE header is almost always within the first KiB of the file
+    let mut header = vec![0u8; 1024];
+    let read = match File::open(exe).await {
+        Ok(mut file) => file.read(&mut header).await.unwrap_or(0),
+        Err(_) => return false,
+    };
+    header.truncate(read);
+    let pe_offset = match header.get(0x3c..0x40) {
+        Some(offset) => u32::from_le_bytes(offset.try_into().unwrap()) as usize,
+        None => return false,
+    };
+    match header.get(pe_offset..pe_offset + 6) {
+        Some([b'P', b'E', 0, 0, machine @ ..]) => {
+            u16::from_le_bytes([machine[0], machine[1]]) == IMAGE_FILE_MACHINE_I386
+        }
+        _ => false,
+    }
+}
+
+/// Looks for the executable of a native Linux build: the first ELF program that isn't a shared
+/// library, checking shallower directories first.
+#[cfg(target_os = "linux")]
+pub(crate) async fn find_elf_recursive(path: &Path) -> Option<PathBuf> {
+    find_program_recursive(path, |magic| magic == b"\x7fELF").await
+}
+
+/// Looks for the executable of a native macOS build that isn't packaged as an `.app`: the first
+/// Mach-O program that isn't a shared library, checking shallower directories first.
+#[cfg(target_os = "macos")]
+pub(crate) async fn find_mach_o_recursive(path: &Path) -> Option<PathBuf> {
+    find_program_recursive(path, |magic| {
+        matches!(
+            magic,
+            [0xcf | 0xce, 0xfa, 0xed, 0xfe]
+                | [0xfe, 0xed, 0xfa, 0xcf | 0xce]
+                | [0xca, 0xfe, 0xba, 0xbe]
+        )
+    })
+    .await
+}
+
+/// Breadth-first search for a program whose first four bytes pass `is_program`, skipping shared
+/// libraries.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+async fn find_program_recursive(path: &Path, is_program: fn(&[u8; 4]) -> bool) -> Option<PathBuf> {
+    use tokio::io::AsyncReadExt;
+
+    let mut dirs = std::collections::VecDeque::from([path.to_path_buf()]);
+    while let Some(dir) = dirs.pop_front() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::warn!("Failed to iterate over {}: {:?}", dir.display(), err);
+                continue;
+            }
+        };
+        let mut subdirs = vec![];
+        let mut programs = vec![];
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                subdirs.push(entry_path);
+                continue;
+            }
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if file_name.contains(".so") || file_name.ends_with(".dylib") {
+                continue;
+            }
+
+            let mut magic = [0u8; 4];
+            let found = match File::open(&entry_path).await {
+                Ok(mut file) => file.read_exact(&mut magic).await.is_ok() && is_program(&magic),
+                Err(_) => false,
+            };
+            if found {
+                status!("Found program: {}", entry_path.display());
+                programs.push(entry_path);
+            }
+        }
+
+        if !programs.is_empty() {
+            programs.sort();
+            return Some(programs.swap_remove(0));
+        }
+        subdirs.sort();
+        dirs.extend(subdirs);
+    }
+
+    None
+}
+
+/// Compiled `protected_paths` patterns from the settings config.
+pub(crate) struct ProtectedPaths(Vec<glob::Pattern>);
+
+impl ProtectedPaths {
+    pub(crate) fn new(patterns: &[String]) -> Self {
+        Self(
+            patterns
+                .iter()
+                .filter_map(|pattern| match glob::Pattern::new(pattern) {
+                    Ok(pattern) => Some(pattern),
+                    Err(err) => {
+                        println!("Ignoring invalid protected path {pattern}: {err}");
+                        None
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Checks if `file_name`, relative to the install directory, is protected.
+    pub(crate) fn matches(&self, file_name: &str) -> bool {
+        let file_name = file_name.replace('\\', "/");
+        let options = glob::MatchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        self.0
+            .iter()
+            .any(|pattern| pattern.matches_with(&file_name, options))
+    }
+}
+
+/// A build manifest record as written by `install --manifest-only` to a JSON file
+#[derive(serde::Deserialize)]
+struct JsonManifestRecord {
+    file_name: String,
+    size_in_bytes: usize,
+    chunks: usize,
+    sha: String,
+    flags: u8,
+    tag: Option<ChangeTag>,
+}
+
+/// Path of a file `install --manifest-only` saves next to the manifest at `manifest`.
+pub(crate) fn manifest_file(manifest: &Path, name: &str) -> PathBuf {
+    let file_name = manifest
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    manifest.with_file_name(format!("{file_name}.{name}"))
+}
+
+/// Reads a build manifest written by `install --manifest-only` along with the build it
+/// describes and its chunks manifest, saved next to it. The manifest is converted back to CSV
+/// if it was written as JSON.
+pub(crate) async fn read_local_manifest(
+    path: &Path,
+) -> Result<(SavedManifest, Vec<u8>, Vec<u8>), FreeCarnivalError> {
+    let saved = match tokio::fs::read(manifest_file(path, "build.json")).await {
+        Ok(json) => serde_json::from_slice(&json)
+            .map_err(|err| FreeCarnivalError::Verify(format!("Invalid manifest build: {err}")))?,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(FreeCarnivalError::NoManifestBuild(path.to_owned()));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let chunks = match tokio::fs::read(manifest_file(path, "chunks.csv")).await {
+        Ok(chunks) => chunks,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(FreeCarnivalError::NoManifestBuild(path.to_owned()));
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let invalid =
+        |name: &str, err: csv::Error| FreeCarnivalError::Verify(format!("Invalid {name}: {err}"));
+    // The manifests are parsed with `expect` later on, so anything that doesn't parse is caught
+    // here instead
+    for record in csv::Reader::from_reader(&chunks[..]).byte_records() {
+        record
+            .and_then(|record| record.deserialize::<BuildManifestChunksRecord>(None))
+            .map_err(|err| invalid("chunks manifest", err))?;
+    }
+
+    let bytes = tokio::fs::read(path).await?;
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if !is_json {
+        for record in csv::Reader::from_reader(&bytes[..]).byte_records() {
+            let mut record = record.map_err(|err| invalid("manifest", err))?;
+            if record.get(5).is_none() {
+                record.push_field(b"");
+            }
+            record
+                .deserialize::<BuildManifestRecord>(None)
+                .map_err(|err| invalid("manifest", err))?;
+        }
+        return Ok((saved, bytes, chunks));
+    }
+
+    let records: Vec<JsonManifestRecord> = serde_json::from_slice(&bytes)
+        .map_err(|err| FreeCarnivalError::Verify(format!("Invalid manifest: {err}")))?;
+    let mut manifest_wtr = csv::Writer::from_writer(vec![]);
+    for record in records {
+        manifest_wtr
+            .serialize(BuildManifestRecord {
+                size_in_bytes: record.size_in_bytes,
+                chunks: record.chunks,
+                sha: record.sha,
+                flags: record.flags,
+                file_name: record.file_name,
+                tag: record.tag,
+            })
+            .expect("Failed to serialize build manifest");
+    }
+    Ok((saved, manifest_wtr.into_inner().unwrap(), chunks))
+}
+
+/// Narrows a build manifest and its chunks manifest down to the records `keep` accepts.
+pub(crate) fn filter_manifests(
+    manifest_bytes: &[u8],
+    manifest_chunks_bytes: &[u8],
+    mut keep: impl FnMut(&BuildManifestRecord) -> bool,
+) -> (Vec<BuildManifestRecord>, Vec<u8>, Vec<u8>) {
+    let mut manifest_rdr = csv::Reader::from_reader(manifest_bytes);
+    let mut selected = vec![];
+    for record in manifest_rdr.byte_records() {
+        let mut record = record.expect("Failed to get byte record");
+        if record.get(5).is_none() {
+            record.push_field(b"");
+        }
+        let record = record
+            .deserialize::<BuildManifestRecord>(None)
+            .expect("Failed to deserialize build manifest");
+        if keep(&record) {
+            selected.push(record);
+        }
+    }
+
+    let mut manifest_wtr = csv::Writer::from_writer(vec![]);
+    for record in &selected {
+        manifest_wtr
+            .serialize(record)
+            .expect("Failed to serialize build manifest");
+    }
+
+    let file_names: HashSet<&String> = selected.iter().map(|r| &r.file_name).collect();
+    let mut chunks_rdr = csv::Reader::from_reader(manifest_chunks_bytes);
+    let mut chunks_wtr = csv::Writer::from_writer(vec![]);
+    for record in chunks_rdr.byte_records() {
+        let record = record
+            .expect("Failed to get byte record")
+            .deserialize::<BuildManifestChunksRecord>(None)
+            .expect("Failed to deserialize build manifest chunks");
+        if file_names.contains(&record.file_path) {
+            chunks_wtr
+                .serialize(&record)
+                .expect("Failed to serialize build manifest chunks");
+        }
+    }
+
+    let manifest_bytes = manifest_wtr.into_inner().unwrap();
+    let chunks_bytes = chunks_wtr.into_inner().unwrap();
+    (selected, manifest_bytes, chunks_bytes)
+}
+
+/// Counts the files inside `dir` and its subdirectories.
+#[async_recursion]
+pub(crate) async fn count_files(dir: &Path) -> tokio::io::Result<u64> {
+    let mut count = 0;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            count += count_files(&entry.path()).await?;
+        } else {
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Adds up the size of the files inside `dir` and its subdirectories.
+#[async_recursion]
+pub(crate) async fn dir_size(dir: &Path) -> tokio::io::Result<u64> {
+    let mut size = 0;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path()).await?;
+        } else {
+            size += metadata.len();
+        }
+    }
+
+    Ok(size)
+}
+
+/// Deletes everything inside `dir` that isn't protected, removing directories left empty.
+/// Every file removed or kept advances `progress`. Returns whether anything was kept.
+///
+/// With `dry_run`, what would be removed or kept is printed instead and nothing is deleted.
+#[async_recursion]
+pub(crate) async fn remove_unprotected(
+    root: &Path,
+    dir: &Path,
+    protected: Option<&'async_recursion ProtectedPaths>,
+    progress: &ProgressBar,
+    dry_run: bool,
+) -> tokio::io::Result<bool> {
+    let mut kept = false;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy();
+        let is_dir = entry.file_type().await?.is_dir();
+        if protected.is_some_and(|protected| protected.matches(&relative)) {
+            if dry_run {
+                println!("Would keep {} (protected)", path.display());
+            } else {
+                status!("Keeping {}", relative);
+            }
+            kept = true;
+            progress.inc(if is_dir { count_files(&path).await? } else { 1 });
+            continue;
+        }
+
+        if is_dir {
+            if remove_unprotected(root, &path, protected, progress, dry_run).await? {
+                kept = true;
+            } else if dry_run {
+                println!("Would remove {}", path.display());
+            } else {
+                tokio::fs::remove_dir(&path).await?;
+            }
+        } else {
+            if dry_run {
+                println!("Would remove {}", path.display());
+            } else {
+                tokio::fs::remove_file(&path).await?;
+            }
+            progress.inc(1);
+        }
+    }
+
+    Ok(kept)
+}
+
+/// Copies everything inside `from` into `to`, overwriting files that are already in `to`.
+#[async_recursion]
+pub(crate) async fn copy_dir(from: &Path, to: &Path) -> tokio::io::Result<()> {
+    tokio::fs::create_dir_all(to).await?;
+    let mut entries = tokio::fs::read_dir(from).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        if entry.file_type().await?.is_dir() {
+            copy_dir(&source, &target).await?;
+        } else {
+            tokio::fs::copy(&source, &target).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves everything inside `from` into `to`, merging it with anything already in `to`, and
+/// removes `from` afterwards. Files are copied instead when both are on different devices.
+#[async_recursion]
+pub(crate) async fn move_dir(from: &Path, to: &Path) -> tokio::io::Result<()> {
+    if !tokio::fs::try_exists(to).await? {
+        match tokio::fs::rename(from, to).await {
+            Err(err) if err.kind() == ErrorKind::CrossesDevices => {}
+            result => return result,
+        }
+    }
+
+    tokio::fs::create_dir_all(to).await?;
+    let mut entries = tokio::fs::read_dir(from).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        if entry.file_type().await?.is_dir() {
+            move_dir(&source, &target).await?;
+            continue;
+        }
+
+        match tokio::fs::rename(&source, &target).await {
+            Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+                tokio::fs::copy(&source, &target).await?;
+                tokio::fs::remove_file(&source).await?;
+            }
+            result => result?,
+        }
+    }
+
+    tokio::fs::remove_dir(from).await
+}
+
 pub(crate) async fn read_or_generate_delta_manifest(
     slug: &String,
     old_manifest_bytes: &[u8],
@@ -92,11 +656,11 @@ pub(crate) async fn read_or_generate_delta_manifest(
     if let Ok(exising_delta) =
         read_build_manifest(&manifest_delta_version, slug, "manifest_delta").await
     {
-        println!("Using existing delta manifest");
+        status!("Using existing delta manifest");
         return Ok(exising_delta);
     }
 
-    println!("Generating delta manifest...");
+    status!("Generating delta manifest...");
     let mut new_manifest_rdr = csv::Reader::from_reader(new_manifest_bytes);
     let new_manifest_iter: Vec<BuildManifestRecord> = new_manifest_rdr
         .byte_records()
@@ -192,11 +756,11 @@ pub(crate) async fn read_or_generate_delta_chunks_manifest(
     if let Ok(exising_delta) =
         read_build_manifest(&manifest_delta_version, slug, "manifest_delta_chunks").await
     {
-        println!("Using existing chunks delta manifest");
+        status!("Using existing chunks delta manifest");
         return Ok(exising_delta);
     }
 
-    println!("Generating chunks delta manifest...");
+    status!("Generating chunks delta manifest...");
     let mut delta_manifest_rdr = csv::Reader::from_reader(delta_manifest_bytes);
     let mut delta_manifest = delta_manifest_rdr.byte_records().map(|r| {
         let record = r.expect("Failed to get byte record");
@@ -226,11 +790,11 @@ pub(crate) async fn read_or_generate_delta_chunks_manifest(
         while current_file.is_directory() || current_file.is_empty() {
             current_file = match delta_manifest.next() {
                 Some(file) => {
-                    println!("Skipping over {}", current_file.file_name);
+                    status!("Skipping over {}", current_file.file_name);
                     file.expect("Failed to deserialize build manifest delta")
                 }
                 None => {
-                    println!("Done processing delta chunks");
+                    status!("Done processing delta chunks");
                     break;
                 }
             };
@@ -245,12 +809,12 @@ pub(crate) async fn read_or_generate_delta_chunks_manifest(
             .expect("Failed to serialize build manifest chunks");
 
         if usize::from(record.id) + 1 == current_file.chunks {
-            println!("Done processing chunks for {}", record.file_path);
+            status!("Done processing chunks for {}", record.file_path);
             // Move on to the next file
             current_file = match delta_manifest.next() {
                 Some(file) => file.expect("Failed to deserialize build manifest delta"),
                 None => {
-                    println!("Done processing delta chunks");
+                    status!("Done processing delta chunks");
                     break;
                 }
             };
@@ -269,14 +833,137 @@ pub(crate) async fn read_or_generate_delta_chunks_manifest(
     Ok(delta_bytes)
 }
 
+/// Turns `path` into an extended-length path on Windows, so files nested deeper than `MAX_PATH`
+/// (260 characters) can still be created and read. Paths are returned unchanged on other
+/// platforms, which don't have this limit.
+///
+/// `OsPath` doesn't understand the `\\?\` prefix, so this must only be applied right before
+/// handing the path to the filesystem.
+pub(crate) fn long_path(path: &Path) -> PathBuf {
+    #[cfg(target_os = "windows")]
+    if let Ok(absolute) = std::path::absolute(path) {
+        let absolute = absolute.to_string_lossy();
+        if absolute.starts_with(r"\\?\") {
+            return PathBuf::from(absolute.into_owned());
+        }
+        return match absolute.strip_prefix(r"\\") {
+            Some(unc) => PathBuf::from(format!(r"\\?\UNC\{unc}")),
+            None => PathBuf::from(format!(r"\\?\{absolute}")),
+        };
+    }
+
+    path.to_path_buf()
+}
+
+/// Whether `a` and `b` are on the same filesystem, so files can be renamed from one to the
+/// other. Both must exist.
+pub(crate) fn same_filesystem(a: &Path, b: &Path) -> std::io::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        Ok(std::fs::metadata(a)?.dev() == std::fs::metadata(b)?.dev())
+    }
+    #[cfg(windows)]
+    {
+        // Drives are separate filesystems
+        let a = std::path::absolute(a)?;
+        let b = std::path::absolute(b)?;
+        Ok(a.components().next() == b.components().next())
+    }
+}
+
+/// Gets how many bytes can be written to the filesystem `path` is on. `path` doesn't need to
+/// exist yet, the space is read from its closest existing ancestor.
+pub(crate) fn available_space(path: &Path) -> std::io::Result<u64> {
+    let path = std::path::absolute(path)?;
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(&path);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = std::ffi::CString::new(existing.as_os_str().as_bytes())?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        // SAFETY: `c_path` is a valid C string and `stat` is a valid statvfs to write to
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        #[allow(clippy::unnecessary_cast)]
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        let wide_path: Vec<u16> = existing.as_os_str().encode_wide().chain([0]).collect();
+        let mut available = 0u64;
+        // SAFETY: `wide_path` is null terminated and `available` is a valid u64 to write to
+        let ok = unsafe {
+            windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW(
+                wide_path.as_ptr(),
+                &mut available,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(available)
+    }
+}
+
+/// Makes sure files can be created inside `path`, creating the directory if it doesn't exist.
+///
+/// This is done before downloading anything so permission problems are reported right away
+/// instead of halfway through an install.
+pub(crate) async fn ensure_writable(path: &Path) -> Result<(), FreeCarnivalError> {
+    let probe = path.join(format!(".{}-write-test", *PROJECT_NAME));
+    let result = async {
+        tokio::fs::create_dir_all(path).await?;
+        tokio::fs::write(&probe, b"").await?;
+        tokio::fs::remove_file(&probe).await
+    }
+    .await;
+
+    result.map_err(|err| FreeCarnivalError::NotWritable(path.to_path_buf(), err))
+}
+
+/// Checks if `path` is on a filesystem that ignores case (the default on macOS and Windows).
+pub(crate) async fn is_case_insensitive(path: &Path) -> tokio::io::Result<bool> {
+    let probe = path.join(format!(".{}-CaseTest", *PROJECT_NAME));
+    tokio::fs::write(&probe, b"").await?;
+    let result = tokio::fs::try_exists(path.join(format!(".{}-casetest", *PROJECT_NAME))).await;
+    tokio::fs::remove_file(&probe).await?;
+    result
+}
+
+/// Finds files whose paths only differ in case, returning each file along with the file listed
+/// after it in the manifest that would overwrite it.
+pub(crate) fn find_case_collisions(records: &[BuildManifestRecord]) -> Vec<(String, String)> {
+    let mut seen: HashMap<String, &String> = HashMap::new();
+    let mut collisions = vec![];
+    for record in records.iter().filter(|r| !r.is_directory()) {
+        let folded = record.file_name.replace('\\', "/").to_lowercase();
+        if let Some(previous) = seen.insert(folded, &record.file_name) {
+            collisions.push((previous.to_owned(), record.file_name.to_owned()));
+        }
+    }
+
+    collisions
+}
+
 pub(crate) async fn store_build_manifest(
     body: &[u8],
     build_number: &String,
     product_slug: &String,
     file_suffix: &str,
 ) -> tokio::io::Result<()> {
-    let project = ProjectDirs::from("rs", "", *PROJECT_NAME).unwrap();
-    let path = project.config_dir().join("manifests").join(product_slug);
+    let path = config_dir().join("manifests").join(product_slug);
     tokio::fs::create_dir_all(&path).await?;
 
     let path = path.join(format!("{}_{}.csv", build_number, file_suffix));
@@ -288,15 +975,14 @@ pub(crate) async fn read_build_manifest(
     product_slug: &String,
     file_suffix: &str,
 ) -> tokio::io::Result<Vec<u8>> {
-    let project = ProjectDirs::from("rs", "", *PROJECT_NAME).unwrap();
-    let path = project
-        .config_dir()
+    let path = config_dir()
         .join("manifests")
         .join(product_slug)
         .join(format!("{}_{}.csv", build_number, file_suffix));
     tokio::fs::read(path).await
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn build_from_manifest(
     client: reqwest::Client,
     product: Arc<Product>,
@@ -305,19 +991,57 @@ pub(crate) async fn build_from_manifest(
     build_manifest_chunks_bytes: &[u8],
     install_path: OsPath,
     install_opts: InstallOpts,
-) -> tokio::io::Result<bool> {
-    let mut write_queue = queue![];
+    download_budget: Arc<Semaphore>,
+    resume: bool,
+) -> Result<DownloadStats, FreeCarnivalError> {
     let mut chunk_queue = queue![];
 
     // Create install directory if it doesn't exist
     tokio::fs::create_dir_all(&install_path).await?;
 
+    // Every chunk written is logged, so an interrupted download can be resumed even after the
+    // process is restarted
+    let progress_log_path = install_path
+        .join(format!(".{}-progress", *PROJECT_NAME))
+        .to_pathbuf();
+    // The log starts with a hash of the chunks manifest, so chunks of another build are never
+    // resumed
+    let build_id = format!("{:x}", Sha256::digest(build_manifest_chunks_bytes));
+    let resumed = if resume {
+        read_progress_log(&progress_log_path, &build_id).await?
+    } else {
+        None
+    };
+    let mut resumed = match resumed {
+        Some(resumed) => {
+            status!("Resuming {} partially downloaded file(s)...", resumed.len());
+            resumed
+        }
+        None => {
+            if resume {
+                status!("The interrupted download was of another build, starting over...");
+            }
+            HashMap::new()
+        }
+    };
+    // The log is written again from what was read, dropping a last line that was cut short
+    let mut log = format!("build\t{build_id}\n");
+    for (file_path, (id, len)) in &resumed {
+        log.push_str(&format!("{id}\t{len}\t{file_path}\n"));
+    }
+    tokio::fs::write(&progress_log_path, log).await?;
+    let progress_log_file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(&progress_log_path)
+        .await?;
+    let progress_log = Arc::new(tokio::sync::Mutex::new(progress_log_file));
+
     let mut file_chunk_num_map = HashMap::new();
     let mut total_bytes = 0u64;
 
-    let m = MultiProgress::new();
+    let m = progress_bars();
 
-    println!("Building folder structure...");
+    status!("Building folder structure...");
     let mut manifest_rdr = csv::Reader::from_reader(build_manifest_bytes);
     let byte_records = manifest_rdr.byte_records();
     #[cfg(target_os = "macos")]
@@ -334,23 +1058,24 @@ pub(crate) async fn build_from_manifest(
 
         if record.tag == Some(ChangeTag::Modified) || record.tag == Some(ChangeTag::Removed) {
             let file_path = install_path.join(&record.file_name);
-            println!("Removing {}", file_path);
+            status!("Removing {}", file_path);
+            let long_file_path = long_path(file_path.to_path());
             if record.is_directory() {
-                println!("{} is a directory", file_path);
+                status!("{} is a directory", file_path);
                 // Is a directory
-                if file_path.exists() && file_path.to_path().is_dir() {
-                    println!("Deleting {}", file_path);
+                if long_file_path.is_dir() {
+                    status!("Deleting {}", file_path);
                     // Delete this directory
-                    tokio::fs::remove_dir_all(file_path).await?;
+                    tokio::fs::remove_dir_all(long_file_path).await?;
                 }
                 continue;
             }
 
-            println!("{} is a file", file_path);
-            if file_path.exists() && file_path.is_file() {
-                println!("Deleting {}", file_path);
+            status!("{} is a file", file_path);
+            if long_file_path.is_file() {
+                status!("Deleting {}", file_path);
                 // Delete this file
-                tokio::fs::remove_file(file_path).await?;
+                tokio::fs::remove_file(long_file_path).await?;
             }
 
             if record.tag == Some(ChangeTag::Removed) {
@@ -358,27 +1083,45 @@ pub(crate) async fn build_from_manifest(
             }
         }
 
-        prepare_file(
-            &install_path,
-            #[cfg(target_os = "macos")]
-            &os,
-            &record.file_name,
-            record.is_directory(),
-            #[cfg(target_os = "macos")]
-            &mut mac_app,
-        )
-        .await?;
+        // Resumed files are cut back to the last chunk that was logged. If the file is shorter
+        // than that, the chunk never made it to disk and the file is downloaded from scratch.
+        let resumed_len = match resumed.get(&record.file_name) {
+            Some(&(_, len)) => {
+                let file_path = long_path(install_path.join(&record.file_name).to_path());
+                match std::fs::OpenOptions::new().write(true).open(&file_path) {
+                    Ok(file) if file.metadata()?.len() >= len => {
+                        file.set_len(len)?;
+                        Some(len)
+                    }
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+        if resumed_len.is_none() {
+            resumed.remove(&record.file_name);
+            prepare_file(
+                &install_path,
+                #[cfg(target_os = "macos")]
+                &os,
+                &record.file_name,
+                record.is_directory(),
+                #[cfg(target_os = "macos")]
+                &mut mac_app,
+            )
+            .await?;
+        }
 
         if !record.is_directory() {
             file_chunk_num_map.insert(record.file_name.clone(), record.chunks);
-            total_bytes += record.size_in_bytes as u64;
+            total_bytes += record.size_in_bytes as u64 - resumed_len.unwrap_or_default();
         }
     }
 
     let dl_sty =
         ProgressStyle::with_template("{wide_msg} Download: {binary_bytes_per_sec}").unwrap();
     let wr_sty = ProgressStyle::with_template(
-        "{wide_msg} Disk: {binary_bytes_per_sec}\n[{percent}%] {wide_bar} {bytes:>7}/{total_bytes:7} [{eta_precise}]",
+        "{wide_msg} Disk: {binary_bytes_per_sec}\n[{percent}%] {wide_bar:.cyan/blue} {bytes:>7}/{total_bytes:7} [{eta_precise}]",
     )
     .unwrap()
     .progress_chars("##-");
@@ -387,7 +1130,13 @@ pub(crate) async fn build_from_manifest(
     let wrt_prog =
         Arc::new(m.insert_after(&dl_prog, ProgressBar::new(total_bytes).with_style(wr_sty)));
 
-    println!("Building queue...");
+    status!("Building queue...");
+    // Every file is written by a single writer so its chunks are appended in order, but
+    // different files can be written in parallel.
+    let write_jobs = install_opts.write_jobs.max(1);
+    let mut write_queues: Vec<Queue<(String, u16, bool)>> =
+        (0..write_jobs).map(|_| queue![]).collect();
+    let mut file_writer_map = HashMap::new();
     let mut manifest_chunks_rdr = csv::Reader::from_reader(build_manifest_chunks_bytes);
     let byte_records = manifest_chunks_rdr.byte_records();
     for record in byte_records {
@@ -396,160 +1145,617 @@ pub(crate) async fn build_from_manifest(
             .deserialize::<BuildManifestChunksRecord>(None)
             .expect("Failed to deserialize chunks manifest");
 
+        if resumed
+            .get(&record.file_path)
+            .is_some_and(|&(written, _)| record.id <= written)
+        {
+            continue;
+        }
+
         let is_last = file_chunk_num_map[&record.file_path] - 1 == usize::from(record.id);
         if is_last {
             file_chunk_num_map.remove(&record.file_path);
         }
-        write_queue
+        let next_writer = file_writer_map.len() % write_jobs;
+        let writer = *file_writer_map
+            .entry(record.file_path.clone())
+            .or_insert(next_writer);
+        write_queues[writer]
             .add((record.sha.clone(), record.id, is_last))
             .unwrap();
-        chunk_queue.add(record).unwrap();
+        chunk_queue.add((writer, record)).unwrap();
     }
     drop(file_chunk_num_map);
+    drop(file_writer_map);
+
+    status!("Spawning {} write thread(s)...", write_jobs);
+    let mut write_handlers = vec![];
+    let write_nanos = Arc::new(AtomicU64::new(0));
+    let mut write_txs = vec![];
+    for write_queue in write_queues {
+        let (tx, rx) =
+            async_channel::bounded::<(BuildManifestChunksRecord, Bytes, OwnedSemaphorePermit)>(
+                install_opts.max_concurrency.max().max(1),
+            );
+        write_txs.push(tx);
+        write_handlers.push(tokio::spawn(write_chunks(
+            install_path.clone(),
+            write_queue,
+            rx,
+            wrt_prog.clone(),
+            progress_log.clone(),
+            write_nanos.clone(),
+        )));
+    }
 
-    let (tx, rx) =
-        async_channel::unbounded::<(BuildManifestChunksRecord, Bytes, OwnedSemaphorePermit)>();
-
-    println!("Spawning write thread...");
-    let write_handler = tokio::spawn(async move {
-        println!("Write thread started.");
-
-        let mut in_buffer = HashMap::new();
-        let mut file_map = HashMap::new();
-
-        while write_queue.size() > 0 {
-            let (record, chunk, permit) = match rx.recv().await {
-                Ok(msg) => msg,
-                Err(_) => {
-                    println!("Write channel has closed");
-                    break;
+    status!("Downloading chunks...");
+    let started_at = Instant::now();
+    let downloaded_bytes = Arc::new(AtomicU64::new(0));
+    let retries = Arc::new(AtomicU64::new(0));
+    let network_nanos = Arc::new(AtomicU64::new(0));
+    let verify_nanos = Arc::new(AtomicU64::new(0));
+    let peak_bytes_per_sec = Arc::new(Mutex::new(0f64));
+    let sampler = {
+        let downloaded_bytes = downloaded_bytes.clone();
+        let peak_bytes_per_sec = peak_bytes_per_sec.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            let mut last_bytes = 0;
+            let mut last_tick = Instant::now();
+            loop {
+                interval.tick().await;
+                let bytes = downloaded_bytes.load(Ordering::Relaxed);
+                let rate = (bytes - last_bytes) as f64 / last_tick.elapsed().as_secs_f64();
+                let mut peak = peak_bytes_per_sec.lock().unwrap();
+                if rate > *peak {
+                    *peak = rate;
                 }
-            };
-
-            // Some files don't have the chunk id in the sha parts, so they can have reused
-            // SHAs for chunks (e.g. DieYoungPrologue-WindowsNoEditor.pak)
-            let chunk_key = format!("{},{}", record.id, record.sha);
-            in_buffer.insert(chunk_key, (record.file_path, chunk, permit));
-
+                last_bytes = bytes;
+                last_tick = Instant::now();
+            }
+        })
+    };
+    let heartbeat = {
+        let wrt_prog = wrt_prog.clone();
+        heartbeat("download", &product.slugged_name, "bytes", move || {
+            (wrt_prog.position(), wrt_prog.length().unwrap_or_default())
+        })
+    };
+    // With `--max-concurrency auto`, the download budget is grown or shrunk every few seconds,
+    // keeping the direction that improved throughput and turning around when it didn't
+    let concurrency = Arc::new(AtomicUsize::new(install_opts.max_concurrency.initial()));
+    let tuner = (install_opts.max_concurrency == Concurrency::Auto).then(|| {
+        let downloaded_bytes = downloaded_bytes.clone();
+        let download_budget = download_budget.clone();
+        let concurrency = concurrency.clone();
+        let dl_prog = dl_prog.clone();
+        let max = install_opts.max_concurrency.max();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(3));
+            interval.tick().await;
+            let mut last_bytes = 0;
+            let mut last_rate = 0f64;
+            let mut growing = true;
             loop {
-                match write_queue.peek() {
-                    Ok((next_chunk, chunk_id, is_last_chunk)) => {
-                        let next_chunk_key = format!("{},{}", chunk_id, next_chunk);
-                        if let Some((file_path, bytes, permit)) = in_buffer.remove(&next_chunk_key)
-                        {
-                            if !file_map.contains_key(&file_path) {
-                                let chunk_file_path = install_path.join(&file_path);
-                                let file = open_file(&chunk_file_path).await.unwrap_or_else(|_| {
-                                    panic!("Failed to open {}", chunk_file_path)
-                                });
-                                file_map.insert(file_path.clone(), file);
-                            }
-                            let file = file_map.get_mut(&file_path).unwrap();
-                            write_queue.remove().unwrap();
-                            // println!("Writing {}", next_chunk);
-                            let bytes_written = bytes.len();
-                            append_chunk(file, bytes).await.unwrap_or_else(|_| {
-                                panic!("Failed to write {}.bin to {}", next_chunk, file_path)
-                            });
-                            drop(permit);
-
-                            wrt_prog.inc(bytes_written as u64);
-
-                            if is_last_chunk {
-                                file_map.remove(&file_path);
-                            }
-
-                            continue;
-                        }
-
-                        // println!(
-                        //     "Not ready to write {}: {} pending",
-                        //     next_chunk,
-                        //     in_buffer.len()
-                        // );
-
-                        break;
-                    }
-                    Err(_) => {
-                        println!("No more chunks to write");
-                        return;
-                    }
+                interval.tick().await;
+                let bytes = downloaded_bytes.load(Ordering::Relaxed);
+                let rate = (bytes - last_bytes) as f64;
+                if rate < last_rate * 1.05 {
+                    growing = !growing;
                 }
+                last_bytes = bytes;
+                last_rate = rate;
+
+                let current = concurrency.load(Ordering::Relaxed);
+                if growing && current < max {
+                    download_budget.add_permits(1);
+                    concurrency.fetch_add(1, Ordering::Relaxed);
+                } else if !growing && current > 1 {
+                    download_budget.acquire().await.unwrap().forget();
+                    concurrency.fetch_sub(1, Ordering::Relaxed);
+                }
+                dl_prog.set_message(format!(
+                    "{} parallel downloads",
+                    concurrency.load(Ordering::Relaxed)
+                ));
             }
-        }
-        println!("Write thread finished.");
+        })
     });
-
-    println!("Downloading chunks...");
     let max_chunks_in_memory = install_opts.max_memory_usage / *MAX_CHUNK_SIZE;
     let mem_semaphore = Arc::new(Semaphore::new(max_chunks_in_memory));
-    let dl_semaphore = Arc::new(Semaphore::new(install_opts.max_download_workers));
-    while let Ok(record) = chunk_queue.remove() {
-        let mem_permit = mem_semaphore.clone().acquire_owned().await.unwrap();
+    // Bumped every time the session is refreshed, so chunks failing at the same time only
+    // refresh it once
+    let session_generation = Arc::new(tokio::sync::Mutex::new(0u64));
+    let stall_timeout = Duration::from_secs(install_opts.timeout_per_chunk);
+    let max_retries = install_opts.retries;
+    let rate_limit = install_opts
+        .limit_rate
+        .map(|bytes_per_sec| Arc::new(RateLimiter::new(bytes_per_sec)));
+    let mut downloads = JoinSet::new();
+    while let Ok((writer, record)) = chunk_queue.remove() {
+        // A failed chunk closes the semaphore, so no more chunks are queued
+        let Ok(mem_permit) = mem_semaphore.clone().acquire_owned().await else {
+            break;
+        };
+        let mem_semaphore = mem_semaphore.clone();
         let client = client.clone();
         let product = product.clone();
         let os = os.clone();
-        let thread_tx = tx.clone();
+        let thread_tx = write_txs[writer].clone();
         let dl_prog = dl_prog.clone();
-        let dl_semaphore = dl_semaphore.clone();
-
-        tokio::spawn(async move {
-            // println!("Downloading {}", record.sha);
-            let dl_permit = dl_semaphore.acquire().await.unwrap();
-            let chunk = api::product::download_chunk(&client, &product, &os, &record.sha)
+        let dl_semaphore = download_budget.clone();
+        let downloaded_bytes = downloaded_bytes.clone();
+        let session_generation = session_generation.clone();
+        let retries = retries.clone();
+        let network_nanos = network_nanos.clone();
+        let verify_nanos = verify_nanos.clone();
+        let rate_limit = rate_limit.clone();
+
+        downloads.spawn(async move {
+            log::debug!("Downloading {}", record.sha);
+            // A chunk that fails verification (e.g. a truncated response) is downloaded again
+            // before anything is written, so corruption is caught without a second read pass
+            let mut corrupt_downloads = 0;
+            let chunk = loop {
+                let dl_permit = dl_semaphore.acquire().await.unwrap();
+                let download_started_at = Instant::now();
+                let chunk = match download_chunk_refreshing_session(
+                    &client,
+                    &product,
+                    &os,
+                    &record.sha,
+                    &session_generation,
+                    &retries,
+                    stall_timeout,
+                    max_retries,
+                    rate_limit.as_deref(),
+                )
                 .await
-                .unwrap_or_else(|_| panic!("Failed to download {}.bin", &record.sha));
-            drop(dl_permit);
+                {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        mem_semaphore.close();
+                        return Err(err);
+                    }
+                };
+                drop(dl_permit);
+                add_elapsed(&network_nanos, download_started_at);
+                downloaded_bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
 
-            dl_prog.inc(chunk.len() as u64);
+                if install_opts.skip_verify {
+                    break chunk;
+                }
 
-            if !install_opts.skip_verify {
-                let chunk_parts = &record.sha.split('_').collect::<Vec<&str>>();
-                match chunk_parts.last() {
-                    Some(chunk_sha) => {
-                        // println!("Verifying {}", record.sha);
-                        let chunk_corrupted = !verify_chunk(&chunk, chunk_sha);
-
-                        if chunk_corrupted {
-                            println!("Sha: {}", chunk_sha);
-                            println!(
-                                "{} failed verification. {} is corrupted.",
-                                &record.sha, &record.file_path
-                            );
-                            return false;
-                        }
-                    }
-                    None => {
-                        println!("Couldn't find Chunk SHA. Skipping verification...");
+                log::trace!("Verifying {}", record.sha);
+                let verify_started_at = Instant::now();
+                let chunk_corrupted = match verify_chunk(&chunk, chunk_sha(&record.sha)) {
+                    Ok(valid) => !valid,
+                    Err(err) => {
+                        log::warn!("{}", err);
+                        true
                     }
+                };
+                add_elapsed(&verify_nanos, verify_started_at);
+
+                if !chunk_corrupted {
+                    break chunk;
                 }
-            }
+                if corrupt_downloads < max_retries {
+                    corrupt_downloads += 1;
+                    retries.fetch_add(1, Ordering::Relaxed);
+                    status!(
+                        "{} failed verification, downloading it again...",
+                        &record.sha
+                    );
+                    continue;
+                }
+                mem_semaphore.close();
+                return Err(FreeCarnivalError::Verify(format!(
+                    "Chunk {} failed verification {} times. {} is corrupted.",
+                    &record.sha,
+                    corrupt_downloads + 1,
+                    &record.file_path
+                )));
+            };
+            dl_prog.inc(chunk.len() as u64);
 
-            thread_tx.send((record, chunk, mem_permit)).await.unwrap();
+            // The write thread only stops early when writing failed, its error is returned
+            // once it's joined
+            if thread_tx.send((record, chunk, mem_permit)).await.is_err() {
+                mem_semaphore.close();
+                return Err(FreeCarnivalError::Io(std::io::Error::new(
+                    ErrorKind::BrokenPipe,
+                    "The write thread stopped",
+                )));
+            }
 
-            true
+            Ok(())
         });
     }
 
-    println!("Waiting for write thread to finish...");
-    write_handler.await?;
+    // The first chunk that fails cancels the ones still downloading. What was written up to
+    // then is in the progress log, so the install can be resumed.
+    let mut failure = None;
+    while let Some(result) = downloads.join_next().await {
+        let err = match result {
+            Ok(Ok(())) => continue,
+            Ok(Err(err)) => err,
+            Err(err) if err.is_cancelled() => continue,
+            Err(err) => std::io::Error::from(err).into(),
+        };
+        if failure.is_none() {
+            downloads.abort_all();
+            failure = Some(err);
+        }
+    }
+    drop(write_txs);
+
+    status!("Waiting for write threads to finish...");
+    for write_handler in write_handlers {
+        let result = match write_handler.await {
+            Ok(result) => result,
+            Err(err) => Err(std::io::Error::from(err).into()),
+        };
+        // A failed write is what stopped the downloads, so it's the error reported
+        if let Err(err) = result {
+            failure = Some(err);
+        }
+    }
+    sampler.abort();
+    if let Some(heartbeat) = heartbeat {
+        heartbeat.abort();
+    }
+    if let Some(tuner) = tuner {
+        tuner.abort();
+        let _ = tuner.await;
+        // The budget can be shared with the next game of this run, so it's put back to where
+        // it started
+        let settled = concurrency.load(Ordering::Relaxed);
+        let initial = install_opts.max_concurrency.initial();
+        status!("Settled on {settled} parallel downloads");
+        if settled > initial {
+            download_budget
+                .acquire_many((settled - initial) as u32)
+                .await
+                .unwrap()
+                .forget();
+        } else {
+            download_budget.add_permits(initial - settled);
+        }
+    }
+    if let Some(err) = failure {
+        return Err(err);
+    }
+    drop(progress_log);
+    tokio::fs::remove_file(&progress_log_path).await?;
 
     #[cfg(target_os = "macos")]
     if *os == BuildOs::Mac {
         mac_app.mark_as_executable().await?;
     }
 
-    // TODO: Redo logic for verification
-    Ok(true)
+    let elapsed_secs = started_at.elapsed().as_secs_f64();
+    let total_bytes = downloaded_bytes.load(Ordering::Relaxed);
+    if let Err(err) = UsageConfig::record(total_bytes) {
+        log::warn!("Failed to record download usage: {err}");
+    }
+    let average_bytes_per_sec = if elapsed_secs > 0f64 {
+        total_bytes as f64 / elapsed_secs
+    } else {
+        0f64
+    };
+    let peak_bytes_per_sec = peak_bytes_per_sec
+        .lock()
+        .unwrap()
+        .max(average_bytes_per_sec);
+
+    Ok(DownloadStats {
+        total_bytes,
+        elapsed_secs,
+        average_bytes_per_sec,
+        peak_bytes_per_sec,
+        retries: retries.load(Ordering::Relaxed),
+        timings: PhaseTimings {
+            network_secs: nanos_to_secs(&network_nanos),
+            verify_secs: nanos_to_secs(&verify_nanos),
+            write_secs: nanos_to_secs(&write_nanos),
+            elapsed_secs,
+            ..Default::default()
+        },
+    })
+}
+
+/// Adds the time elapsed since `started_at` to a nanosecond counter.
+fn add_elapsed(nanos: &AtomicU64, started_at: Instant) {
+    nanos.fetch_add(started_at.elapsed().as_nanos() as u64, Ordering::Relaxed);
+}
+
+fn nanos_to_secs(nanos: &AtomicU64) -> f64 {
+    Duration::from_nanos(nanos.load(Ordering::Relaxed)).as_secs_f64()
+}
+
+/// Token bucket shared by chunk downloads, so their combined throughput stays under
+/// `--limit-rate` no matter how many run at one time.
+pub(crate) struct RateLimiter {
+    bytes_per_sec: u64,
+    /// When the bytes taken so far are paid for. Time spent idle before now is banked for up
+    /// to a second, so bursts stay short.
+    next_free: tokio::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            next_free: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until `bytes` more can be received without going over the limit.
+    pub(crate) async fn take(&self, bytes: usize) {
+        let wait_until = {
+            let mut next_free = self.next_free.lock().await;
+            let now = Instant::now();
+            let banked = now.checked_sub(Duration::from_secs(1)).unwrap_or(now);
+            *next_free = (*next_free).max(banked)
+                + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            *next_free
+        };
+        tokio::time::sleep_until(wait_until.into()).await;
+    }
+}
+
+/// Downloads a chunk, refreshing the session and retrying when the server rejects the request
+/// because the session expired partway through a long download. Chunks that stall are retried
+/// too, and transient failures are retried up to `max_retries` times with exponential backoff.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn download_chunk_refreshing_session(
+    client: &reqwest::Client,
+    product: &Product,
+    os: &BuildOs,
+    chunk_sha: &String,
+    session_generation: &tokio::sync::Mutex<u64>,
+    retries: &AtomicU64,
+    stall_timeout: Duration,
+    max_retries: usize,
+    rate_limit: Option<&RateLimiter>,
+) -> Result<Bytes, FreeCarnivalError> {
+    let mut refreshes = 0;
+    let mut stalls = 0;
+    let mut failures = 0;
+    loop {
+        let generation = *session_generation.lock().await;
+        match api::product::download_chunk(
+            client,
+            product,
+            os,
+            chunk_sha,
+            stall_timeout,
+            rate_limit,
+        )
+        .await
+        {
+            Err(
+                err @ FreeCarnivalError::Http {
+                    status: StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN,
+                    ..
+                },
+            ) if refreshes < *MAX_SESSION_REFRESHES => {
+                refreshes += 1;
+                retries.fetch_add(1, Ordering::Relaxed);
+
+                let mut current_generation = session_generation.lock().await;
+                if *current_generation == generation {
+                    status!("Download session expired, refreshing...");
+                    // Without a new session the chunk would be rejected again
+                    if let Err(refresh_err) = api::auth::sync_relogging_in(client, true).await {
+                        log::warn!("Failed to refresh session: {refresh_err}");
+                        return Err(err);
+                    }
+                    *current_generation += 1;
+                }
+            }
+            Err(err @ FreeCarnivalError::ChunkStalled(..)) if stalls < *MAX_CHUNK_STALLS => {
+                stalls += 1;
+                retries.fetch_add(1, Ordering::Relaxed);
+                status!("{err}, retrying...");
+            }
+            Err(err) if err.is_transient() && failures < max_retries => {
+                let backoff = Duration::from_millis(500 << failures.min(16))
+                    .min(Duration::from_secs(*MAX_RETRY_BACKOFF));
+                failures += 1;
+                retries.fetch_add(1, Ordering::Relaxed);
+                status!("{err}, retrying in {:.1}s...", backoff.as_secs_f64());
+                tokio::time::sleep(backoff).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Environment variables that make a game render on `gpu`. `nvidia` turns on NVIDIA PRIME
+/// render offload, anything else is a Mesa DRI_PRIME value (a GPU index or PCI tag).
+#[cfg(target_os = "linux")]
+pub(crate) fn gpu_env(gpu: &str) -> Vec<(&'static str, String)> {
+    if gpu.eq_ignore_ascii_case("nvidia") {
+        return vec![
+            ("__NV_PRIME_RENDER_OFFLOAD", "1".to_owned()),
+            ("__GLX_VENDOR_LIBRARY_NAME", "nvidia".to_owned()),
+            ("__VK_LAYER_NV_optimus", "NVIDIA_only".to_owned()),
+        ];
+    }
+    vec![("DRI_PRIME", gpu.to_owned())]
+}
+
+/// Reads the progress log of an interrupted download of build `build_id`, returning the id of
+/// the last chunk written to each file and the file's length after writing it. Returns `None`
+/// if the log is of another build.
+async fn read_progress_log(
+    path: &Path,
+    build_id: &str,
+) -> Result<Option<HashMap<String, (u16, u64)>>, FreeCarnivalError> {
+    let log = match tokio::fs::read(path).await {
+        Ok(log) => log,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Some(HashMap::new())),
+        Err(err) => return Err(err.into()),
+    };
+    let corrupt = |reason: &str| FreeCarnivalError::ResumeState(path.to_owned(), reason.to_owned());
+    let mut lines: Vec<&[u8]> = log.split(|&b| b == b'\n').collect();
+    // The last line can be cut short if the process was killed while writing it
+    lines.pop();
+    let mut lines = lines
+        .into_iter()
+        .map(|line| std::str::from_utf8(line).map_err(|_| corrupt("it isn't valid UTF-8")));
+    match lines.next().transpose()? {
+        Some(header) => match header.strip_prefix("build\t") {
+            Some(id) if id == build_id => {}
+            Some(_) => return Ok(None),
+            None => return Err(corrupt("it doesn't start with a build line")),
+        },
+        None => return Ok(Some(HashMap::new())),
+    }
+
+    let mut progress = HashMap::new();
+    for line in lines {
+        let line = line?;
+        let mut fields = line.splitn(3, '\t');
+        let (Some(id), Some(len), Some(file_path)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(corrupt(&format!("malformed line \"{line}\"")));
+        };
+        let (Ok(id), Ok(len)) = (id.parse(), len.parse()) else {
+            return Err(corrupt(&format!("malformed line \"{line}\"")));
+        };
+        progress.insert(file_path.to_owned(), (id, len));
+    }
+
+    Ok(Some(progress))
+}
+
+/// Appends downloaded chunks to their files in the order given by `write_queue`, buffering any
+/// chunk that arrives before the ones preceding it. Every chunk written is logged to
+/// `progress_log`. Stops at the first file that can't be opened or written to.
+async fn write_chunks(
+    install_path: OsPath,
+    mut write_queue: Queue<(String, u16, bool)>,
+    rx: async_channel::Receiver<(BuildManifestChunksRecord, Bytes, OwnedSemaphorePermit)>,
+    wrt_prog: Arc<ProgressBar>,
+    progress_log: Arc<tokio::sync::Mutex<File>>,
+    write_nanos: Arc<AtomicU64>,
+) -> Result<(), FreeCarnivalError> {
+    status!("Write thread started.");
+
+    let mut in_buffer = HashMap::new();
+    let mut file_map = HashMap::new();
+
+    while write_queue.size() > 0 {
+        let (record, chunk, permit) = match rx.recv().await {
+            Ok(msg) => msg,
+            Err(_) => {
+                log::error!("Write channel has closed");
+                break;
+            }
+        };
+
+        // Some files don't have the chunk id in the sha parts, so they can have reused
+        // SHAs for chunks (e.g. DieYoungPrologue-WindowsNoEditor.pak)
+        let chunk_key = format!("{},{}", record.id, record.sha);
+        in_buffer.insert(chunk_key, (record.file_path, chunk, permit));
+
+        loop {
+            match write_queue.peek() {
+                Ok((next_chunk, chunk_id, is_last_chunk)) => {
+                    let next_chunk_key = format!("{},{}", chunk_id, next_chunk);
+                    if let Some((file_path, bytes, permit)) = in_buffer.remove(&next_chunk_key) {
+                        if !file_map.contains_key(&file_path) {
+                            let chunk_file_path = install_path.join(&file_path);
+                            let file = open_file(&chunk_file_path).await.map_err(|err| {
+                                std::io::Error::new(
+                                    err.kind(),
+                                    format!("Failed to open {}: {err}", chunk_file_path),
+                                )
+                            })?;
+                            file_map.insert(file_path.clone(), file);
+                        }
+                        let file = file_map.get_mut(&file_path).unwrap();
+                        write_queue.remove().unwrap();
+                        let bytes_written = bytes.len();
+                        let write_started_at = Instant::now();
+                        append_chunk(file, bytes).await.map_err(|err| {
+                            std::io::Error::new(
+                                err.kind(),
+                                format!(
+                                    "Failed to write {}.bin to {}: {err}",
+                                    next_chunk, file_path
+                                ),
+                            )
+                        })?;
+                        add_elapsed(&write_nanos, write_started_at);
+                        drop(permit);
+                        log_chunk(&progress_log, file, chunk_id, &file_path)
+                            .await
+                            .unwrap_or_else(|err| {
+                                log::warn!("Failed to save download progress: {err}")
+                            });
+
+                        wrt_prog.inc(bytes_written as u64);
+
+                        if is_last_chunk {
+                            file_map.remove(&file_path);
+                        }
+
+                        continue;
+                    }
+
+                    break;
+                }
+                Err(_) => {
+                    status!("No more chunks to write");
+                    return Ok(());
+                }
+            }
+        }
+    }
+    status!("Write thread finished.");
+    Ok(())
+}
+
+/// Records that chunk `chunk_id` of `file_path` was written. The chunk is flushed first, so the
+/// log never gets ahead of the file.
+async fn log_chunk(
+    progress_log: &tokio::sync::Mutex<File>,
+    file: &mut File,
+    chunk_id: u16,
+    file_path: &str,
+) -> tokio::io::Result<()> {
+    file.flush().await?;
+    let len = file.metadata().await?.len();
+    let line = format!("{chunk_id}\t{len}\t{file_path}\n");
+    progress_log.lock().await.write_all(line.as_bytes()).await
 }
 
 pub(crate) async fn open_file(file_path: &OsPath) -> tokio::io::Result<File> {
     tokio::fs::OpenOptions::new()
         .append(true)
-        .open(file_path)
+        .open(long_path(file_path.to_path()))
         .await
 }
 
+/// Writes `bytes` over the part of `file_path` starting at `offset`.
+pub(crate) async fn write_chunk_at(
+    file_path: &OsPath,
+    offset: u64,
+    bytes: &[u8],
+) -> tokio::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(long_path(file_path.to_path()))
+        .await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    file.write_all(bytes).await?;
+    file.flush().await
+}
+
 pub(crate) async fn append_chunk(
     file: &mut tokio::fs::File,
     chunk: Bytes,
@@ -568,12 +1774,12 @@ pub(crate) async fn prepare_file(
 
     // File is a directory. We should create this directory.
     if is_directory {
-        if !file_path.exists() {
-            tokio::fs::create_dir(&file_path).await?;
+        if !long_path(file_path.to_path()).exists() {
+            tokio::fs::create_dir(long_path(file_path.to_path())).await?;
         }
     } else {
         // Create empty file.
-        tokio::fs::File::create(&file_path).await?;
+        tokio::fs::File::create(long_path(file_path.to_path())).await?;
     }
 
     #[cfg(target_os = "macos")]
@@ -589,23 +1795,135 @@ pub(crate) async fn prepare_file(
     Ok(())
 }
 
-pub(crate) fn verify_file_hash(file_path: &OsPath, sha: &str) -> std::io::Result<bool> {
-    let mut file = std::fs::File::open(file_path)?;
-    let mut hasher = Sha256::new();
-    std::io::copy(&mut file, &mut hasher)?;
-    let hash = hasher.finalize();
-    let file_sha = base16ct::lower::encode_string(&hash);
+/// Hash algorithms a manifest digest can be written in.
+///
+/// Manifests don't have a column for the algorithm, so it's read from an optional `algo:`
+/// prefix on the digest, or otherwise inferred from the length of the hex string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Splits a manifest digest into its algorithm and hex string.
+    pub(crate) fn detect(digest: &str) -> Result<(Self, &str), FreeCarnivalError> {
+        let (prefix, hex) = match digest.split_once(':') {
+            Some((prefix, hex)) => (Some(prefix.to_lowercase()), hex),
+            None => (None, digest),
+        };
+        let algorithm = match (prefix.as_deref(), hex.len()) {
+            (Some("md5"), _) | (None, 32) => Self::Md5,
+            (Some("sha1"), _) | (None, 40) => Self::Sha1,
+            (Some("sha256"), _) | (None, 64) => Self::Sha256,
+            _ => return Err(FreeCarnivalError::UnknownHashAlgorithm(digest.to_owned())),
+        };
+
+        Ok((algorithm, hex))
+    }
+
+    fn hash_reader(&self, reader: &mut impl std::io::Read) -> std::io::Result<String> {
+        fn digest<D: Digest + std::io::Write>(
+            reader: &mut impl std::io::Read,
+        ) -> std::io::Result<String> {
+            let mut hasher = D::new();
+            std::io::copy(reader, &mut hasher)?;
+            Ok(base16ct::lower::encode_string(&hasher.finalize()))
+        }
 
-    Ok(file_sha == sha)
+        match self {
+            Self::Md5 => digest::<Md5>(reader),
+            Self::Sha1 => digest::<Sha1>(reader),
+            Self::Sha256 => digest::<Sha256>(reader),
+        }
+    }
 }
 
-pub(crate) fn verify_chunk(chunk: &Bytes, sha: &str) -> bool {
-    let mut hasher = Sha256::new();
-    hasher.update(chunk);
-    let hash = hasher.finalize();
-    let sha_str = base16ct::lower::encode_string(&hash);
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Md5 => "MD5",
+                Self::Sha1 => "SHA1",
+                Self::Sha256 => "SHA256",
+            }
+        )
+    }
+}
 
-    sha_str == sha
+pub(crate) fn verify_file_hash(file_path: &OsPath, sha: &str) -> Result<bool, FreeCarnivalError> {
+    let (algorithm, expected) = HashAlgorithm::detect(sha)?;
+    let mut file = std::fs::File::open(long_path(file_path.to_path()))?;
+    let file_sha = algorithm.hash_reader(&mut file)?;
+
+    if file_sha != expected.to_lowercase() {
+        log::warn!(
+            "{} {} mismatch: expected {}, got {}",
+            file_path,
+            algorithm,
+            expected,
+            file_sha
+        );
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+/// Hashes `file_path` one chunk at a time, returning the ids of the `chunks` (sorted by id) that
+/// don't match. Returns `None` when the file has to be downloaded whole instead, because it's
+/// missing, has the wrong size or isn't split in `MAX_CHUNK_SIZE` chunks, so its chunks can't be
+/// located.
+pub(crate) fn find_bad_chunks(
+    file_path: &OsPath,
+    size: u64,
+    chunks: &[BuildManifestChunksRecord],
+) -> Result<Option<Vec<u16>>, FreeCarnivalError> {
+    use std::io::Read;
+
+    let max_chunk_size = *MAX_CHUNK_SIZE as u64;
+    if chunks.len() as u64 != size.div_ceil(max_chunk_size)
+        || chunks
+            .iter()
+            .enumerate()
+            .any(|(index, chunk)| usize::from(chunk.id) != index)
+    {
+        return Ok(None);
+    }
+    let mut file = match std::fs::File::open(long_path(file_path.to_path())) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if file.metadata()?.len() != size {
+        return Ok(None);
+    }
+
+    let mut bad = vec![];
+    for chunk in chunks {
+        let mut block = vec![];
+        (&mut file).take(max_chunk_size).read_to_end(&mut block)?;
+        if !verify_chunk(&Bytes::from(block), chunk_sha(&chunk.sha))? {
+            bad.push(chunk.id);
+        }
+    }
+
+    Ok(Some(bad))
+}
+
+/// The hash of a chunk's contents, which is the last part of its name.
+pub(crate) fn chunk_sha(sha: &str) -> &str {
+    sha.rsplit('_').next().unwrap_or(sha)
+}
+
+pub(crate) fn verify_chunk(chunk: &Bytes, sha: &str) -> Result<bool, FreeCarnivalError> {
+    let (algorithm, expected) = HashAlgorithm::detect(sha)?;
+    let sha_str = algorithm.hash_reader(&mut &chunk[..])?;
+
+    Ok(sha_str == expected.to_lowercase())
 }
 
 #[cfg(target_os = "macos")]
@@ -615,6 +1933,8 @@ pub(crate) mod mac {
     use async_recursion::async_recursion;
     use serde::Deserialize;
 
+    use crate::output::status;
+
     #[async_recursion]
     pub(crate) async fn find_app_recursive(path: &PathBuf) -> Option<PathBuf> {
         let mut subdirs = vec![];
@@ -624,7 +1944,7 @@ pub(crate) mod mac {
                 while let Ok(Some(entry)) = subpath.next_entry().await {
                     let entry_path = entry.path();
                     // Check if the current path is a .app extension
-                    println!("Checking file: {}", entry_path.display());
+                    status!("Checking file: {}", entry_path.display());
                     if let Some(ext) = entry_path.extension() {
                         if ext == "app" {
                             return Some(entry_path);
@@ -637,12 +1957,12 @@ pub(crate) mod mac {
                 }
             }
             Err(err) => {
-                println!("Failed to iterate over {}: {:?}", path.display(), err);
+                log::warn!("Failed to iterate over {}: {:?}", path.display(), err);
             }
         }
 
         for dir in subdirs {
-            println!("Checking directory: {}", dir.display());
+            status!("Checking directory: {}", dir.display());
             if let Some(app_path) = find_app_recursive(&dir.to_path_buf()).await {
                 return Some(app_path);
             }
@@ -677,7 +1997,7 @@ pub(crate) mod mac {
         pub(crate) fn executable(&self) -> Option<PathBuf> {
             match &self.plist {
                 Some(plist_path) => {
-                    let plist: BasicInfoPlist = plist::from_file(plist_path).unwrap();
+                    let plist: BasicInfoPlist = plist::from_file(plist_path).ok()?;
                     let executable_path = plist_path
                         .parent()
                         .unwrap()
@@ -711,3 +2031,282 @@ pub(crate) mod mac {
         app_path.join("Contents").join("Info.plist")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    fn test_product(name: &str) -> Product {
+        Product {
+            namespace: format!("test-{name}"),
+            slugged_name: name.to_owned(),
+            id: 0,
+            name: name.to_owned(),
+            id_key_name: name.to_owned(),
+            version: vec![],
+        }
+    }
+
+    /// Path `download_chunk` requests `chunk_sha` of `product` from
+    fn chunk_path(product: &Product, os: &BuildOs, chunk_sha: &str) -> String {
+        format!(
+            "/DevShowCaseSourceVolume/dev_fold_{}/{}/{}/{}",
+            product.namespace, product.id_key_name, os, chunk_sha,
+        )
+    }
+
+    fn test_install_opts() -> InstallOpts {
+        InstallOpts {
+            max_concurrency: Concurrency::Fixed(8),
+            max_memory_usage: *MAX_CHUNK_SIZE * 64,
+            write_jobs: 2,
+            info: false,
+            skip_verify: false,
+            timeout_per_chunk: 10,
+            retries: 0,
+            limit_rate: None,
+            profile: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn chunks_arriving_out_of_order_are_written_at_their_offsets() {
+        let product = test_product("shuffled");
+        let os = BuildOs::Windows;
+        let files = [("first.bin", 6u16), ("second.bin", 5u16)];
+
+        let mut manifest = String::from("Size in Bytes,Chunks,SHA,Flags,File Name,Change Tag\n");
+        let mut manifest_chunks = String::from("ID,Filepath,Chunk SHA\n");
+        let mut expected = vec![];
+        let mut served = 0u64;
+        for (file_name, chunks) in files {
+            let mut contents = vec![];
+            for id in 0..chunks {
+                let chunk = format!("{file_name}:{id};").repeat(100 + id as usize);
+                let sha = format!("{id}_{:x}", Sha256::digest(&chunk));
+                contents.extend_from_slice(chunk.as_bytes());
+                manifest_chunks.push_str(&format!("{id},{file_name},{sha}\n"));
+
+                // Every chunk is answered after a different delay, so they arrive shuffled
+                let delay = Duration::from_millis((served * 7 % 11) * 20);
+                served += 1;
+                test_server::route(&chunk_path(&product, &os, &sha), move || {
+                    std::thread::sleep(delay);
+                    (200, chunk.clone().into_bytes())
+                });
+            }
+            manifest.push_str(&format!(
+                "{},{chunks},{:x},0,{file_name},\n",
+                contents.len(),
+                Sha256::digest(&contents)
+            ));
+            expected.push((file_name, contents));
+        }
+
+        let install_path =
+            std::env::temp_dir().join(format!("freecarnival-test-{}-shuffled", std::process::id()));
+        let stats = build_from_manifest(
+            reqwest::Client::new(),
+            Arc::new(product),
+            Arc::new(os),
+            manifest.as_bytes(),
+            manifest_chunks.as_bytes(),
+            OsPath::from(&install_path),
+            test_install_opts(),
+            Arc::new(Semaphore::new(8)),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let total_bytes: usize = expected.iter().map(|(_, contents)| contents.len()).sum();
+        assert_eq!(stats.total_bytes, total_bytes as u64);
+        for (file_name, contents) in expected {
+            assert_eq!(
+                std::fs::read(install_path.join(file_name)).unwrap(),
+                contents
+            );
+        }
+        std::fs::remove_dir_all(install_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn transient_chunk_failures_are_retried() {
+        let product = test_product("retried");
+        let os = BuildOs::Windows;
+        let chunk = b"retried chunk".to_vec();
+        let sha = format!("0_{:x}", Sha256::digest(&chunk));
+        let requests = Arc::new(AtomicUsize::new(0));
+        test_server::route(&chunk_path(&product, &os, &sha), {
+            let requests = requests.clone();
+            let chunk = chunk.clone();
+            move || match requests.fetch_add(1, Ordering::Relaxed) {
+                0 | 1 => (503, vec![]),
+                _ => (200, chunk.clone()),
+            }
+        });
+
+        let retries = AtomicU64::new(0);
+        let downloaded = download_chunk_refreshing_session(
+            &reqwest::Client::new(),
+            &product,
+            &os,
+            &sha,
+            &tokio::sync::Mutex::new(0),
+            &retries,
+            Duration::from_secs(10),
+            5,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(&downloaded[..], &chunk[..]);
+        assert_eq!(requests.load(Ordering::Relaxed), 3);
+        assert_eq!(retries.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn rejected_chunks_fail_without_retrying() {
+        let product = test_product("rejected");
+        let os = BuildOs::Windows;
+        for status in [401, 403, 404] {
+            let sha = format!("{status}_rejected");
+            let requests = Arc::new(AtomicUsize::new(0));
+            test_server::route(&chunk_path(&product, &os, &sha), {
+                let requests = requests.clone();
+                move || {
+                    requests.fetch_add(1, Ordering::Relaxed);
+                    (status, vec![])
+                }
+            });
+
+            let result = download_chunk_refreshing_session(
+                &reqwest::Client::new(),
+                &product,
+                &os,
+                &sha,
+                &tokio::sync::Mutex::new(0),
+                &AtomicU64::new(0),
+                Duration::from_secs(10),
+                5,
+                None,
+            )
+            .await;
+
+            match result {
+                Err(FreeCarnivalError::Http { status: got, .. }) => assert_eq!(got, status),
+                result => panic!("Expected a {status} error, got {result:?}"),
+            }
+            assert_eq!(requests.load(Ordering::Relaxed), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_log_keeps_non_ascii_file_names() {
+        let dir =
+            std::env::temp_dir().join(format!("freecarnival-test-{}-log", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let log_path = dir.join("progress.log");
+        tokio::fs::write(&log_path, "build\tabc\n").await.unwrap();
+        let progress_log = tokio::sync::Mutex::new(
+            tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&log_path)
+                .await
+                .unwrap(),
+        );
+        let file_name = "Données/セーブ.dat";
+        let mut file = File::create(dir.join("chunk")).await.unwrap();
+        file.write_all(b"chunk").await.unwrap();
+        log_chunk(&progress_log, &mut file, 3, file_name)
+            .await
+            .unwrap();
+        // A line cut short is dropped, even in the middle of a character
+        progress_log
+            .lock()
+            .await
+            .write_all(&"4\t10\tセーブ".as_bytes()[..10])
+            .await
+            .unwrap();
+
+        let progress = read_progress_log(&log_path, "abc").await.unwrap().unwrap();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress.get(file_name), Some(&(3, 5)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn saved_manifests_are_read_with_their_build() {
+        let dir =
+            std::env::temp_dir().join(format!("freecarnival-test-{}-manifest", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let manifest = dir.join("manifest.json");
+        tokio::fs::write(
+            &manifest,
+            r#"[{"file_name": "game.exe", "size_in_bytes": 5, "chunks": 1, "sha": "aaa", "flags": 0, "tag": null}]"#,
+        )
+        .await
+        .unwrap();
+
+        match read_local_manifest(&manifest).await {
+            Err(FreeCarnivalError::NoManifestBuild(path)) => assert_eq!(path, manifest),
+            result => panic!("Expected a missing build error, got {result:?}"),
+        }
+
+        let chunks = "ID,Filepath,Chunk SHA\n0,game.exe,0_aaa\n";
+        tokio::fs::write(manifest_file(&manifest, "chunks.csv"), chunks)
+            .await
+            .unwrap();
+        tokio::fs::write(
+            manifest_file(&manifest, "build.json"),
+            r#"{"slug": "game", "version": "1.0", "os": "win"}"#,
+        )
+        .await
+        .unwrap();
+        let (saved, manifest_bytes, chunks_bytes) = read_local_manifest(&manifest).await.unwrap();
+        assert_eq!(saved.version, "1.0");
+        assert_eq!(chunks_bytes, chunks.as_bytes());
+        let (records, _, _) = filter_manifests(&manifest_bytes, &[], |_| true);
+        assert_eq!(records[0].file_name, "game.exe");
+        assert_eq!(records[0].sha, "aaa");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn malformed_saved_manifests_are_errors() {
+        let dir = std::env::temp_dir().join(format!(
+            "freecarnival-test-{}-malformed",
+            std::process::id()
+        ));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let manifest = dir.join("manifest.csv");
+        tokio::fs::write(
+            manifest_file(&manifest, "build.json"),
+            r#"{"slug": "game", "version": "1.0", "os": "win"}"#,
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            manifest_file(&manifest, "chunks.csv"),
+            "ID,Filepath,Chunk SHA\n0,game.exe,0_aaa\n",
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            &manifest,
+            "Size in Bytes,Chunks,SHA,Flags,File Name,Change Tag\nfive,1,aaa,0,game.exe,\n",
+        )
+        .await
+        .unwrap();
+
+        match read_local_manifest(&manifest).await {
+            Err(FreeCarnivalError::Verify(message)) => {
+                assert!(message.starts_with("Invalid manifest"))
+            }
+            result => panic!("Expected an invalid manifest error, got {result:?}"),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
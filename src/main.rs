@@ -1,209 +1,746 @@
-use std::sync::Arc;
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
 
 use crate::cli::Cli;
 use crate::config::GalaConfig;
 use crate::{api::auth, config::InstalledConfig};
 use api::GalaClient;
-use clap::Parser;
-use cli::Commands;
-use config::{CookieConfig, LibraryConfig, UserConfig};
-use constants::DEFAULT_BASE_INSTALL_PATH;
+use clap::{CommandFactory, Parser};
+use cli::{Commands, InstalledSort, VersionSort};
+use config::{CookieConfig, LibraryConfig, SessionConfig, SettingsConfig, UsageConfig, UserConfig};
+use constants::{BASE_URL, DEFAULT_BASE_INSTALL_PATH};
+use error::FreeCarnivalError;
+use events::Event;
+use helpers::ProtectedPaths;
+use output::{print_output, status};
 use reqwest_cookie_store::CookieStoreMutex;
-use shared::models::api::{LoginResult, SyncResult};
+#[cfg(not(target_os = "windows"))]
+use shared::models::api::BuildOs;
+use shared::models::api::{ProductVersion, SyncResult};
+use shared::models::{
+    AvailableUpdate, GameInfo, InfoEntry, InstalledEntry, InstalledGame, VerifyOutcome,
+    VersionEntry,
+};
+use tokio::sync::Semaphore;
 
 mod api;
 mod cli;
 mod config;
 mod constants;
+mod credentials;
+mod error;
+mod events;
 mod helpers;
+mod output;
 mod shared;
+#[cfg(test)]
+mod test_server;
 mod utils;
 
+/// Loads a config, exiting with an error message when it can't be loaded (e.g. it's corrupted
+/// and wasn't reset). Exiting skips destructors, so the cookies are saved first.
+macro_rules! load_or_exit {
+    ($config:ty, $cookie_saver:ident) => {
+        match <$config>::load() {
+            Ok(config) => config,
+            Err(err) => {
+                println!("Failed to load {}: {err}", <$config>::config_name());
+                drop($cookie_saver);
+                std::process::exit(1);
+            }
+        }
+    };
+}
+
 #[tokio::main]
 async fn main() {
     let args = Cli::parse();
-    let CookieConfig(cookie_store) = CookieConfig::load().expect("Failed to load cookie store");
+    output::init(&args);
+    if let Some(config_dir) = &args.config_dir {
+        config::set_config_dir(config_dir.to_owned());
+    }
+    if let Some(output_dir) = &args.output_dir {
+        config::set_output_dir(output_dir.to_owned());
+    }
+    let CookieConfig(cookie_store) = match CookieConfig::load() {
+        Ok(cookie_config) => cookie_config,
+        Err(err) => {
+            println!("Failed to load cookie store: {err}");
+            std::process::exit(1);
+        }
+    };
     let cookie_store = Arc::new(CookieStoreMutex::new(cookie_store));
-    let client = reqwest::Client::with_gala(&cookie_store);
-
-    if args.needs_sync() {
-        println!("Syncing library...");
-        match api::auth::sync(&client).await {
-            Ok(Some(result)) => save_user_info(&result),
-            Ok(None) => {
-                println!("Failed to sync: your authentication is invalid.");
-                return;
-            }
+    let _cookie_saver = CookieSaver(cookie_store.clone());
+    let settings = load_or_exit!(SettingsConfig, _cookie_saver);
+    let proxy = match api::proxy_from(args.proxy.as_deref()) {
+        Ok(proxy) => proxy,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    let client = reqwest::Client::with_gala(
+        &cookie_store,
+        args.http_version.unwrap_or(settings.http_version),
+        proxy,
+        (args.timeout > 0).then(|| Duration::from_secs(args.timeout)),
+    );
+
+    if args.needs_sync(&settings.skip_sync) {
+        status!("Syncing library...");
+        match api::auth::sync_relogging_in(&client, false).await {
+            Ok(result) => save_user_info(&result),
             Err(err) => {
-                println!("Failed to sync: {err:#?}");
+                println!("Failed to sync: {err}");
                 return;
             }
         };
     }
 
     match args.command {
-        Commands::Login { email, password } => {
+        Commands::Login {
+            email,
+            password,
+            password_stdin,
+            save_to_keyring,
+            from_keyring,
+            remember,
+            code,
+        } => {
+            // Credentials can come from the environment, keeping them out of shell history and
+            // process arguments (e.g. in CI)
+            let from_env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+            let Some(email) = email.or_else(|| from_env("GALA_EMAIL")) else {
+                println!("No email given. Pass it as an argument or set GALA_EMAIL.");
+                // Exiting skips destructors, so the cookies are saved first
+                drop(_cookie_saver);
+                std::process::exit(1);
+            };
+            let password = match password {
+                Some(password) => Some(password),
+                None if from_keyring || password_stdin => None,
+                None => from_env("GALA_PASSWORD"),
+            };
+            let code = code.or_else(|| from_env("GALA_2FA_CODE"));
+            let email = email.trim().to_owned();
+            if !is_valid_email(&email) {
+                println!("{email:?} is not a valid email address");
+                drop(_cookie_saver);
+                std::process::exit(1);
+            }
             let password = match password {
                 Some(password) => password,
+                None if from_keyring => match credentials::load_password(&email).await {
+                    Ok(password) => password,
+                    Err(err) => {
+                        println!("Failed to read password from keyring: {err}");
+                        drop(_cookie_saver);
+                        std::process::exit(1);
+                    }
+                },
+                None if password_stdin => {
+                    let mut password = String::new();
+                    std::io::stdin()
+                        .read_line(&mut password)
+                        .expect("Failed to read from stdin");
+                    password.trim_end_matches(['\r', '\n']).to_owned()
+                }
+                // Prompting without anyone to answer would hang forever, e.g. in CI
+                None if !std::io::stdin().is_terminal() => {
+                    println!(
+                        "No password given and stdin isn't a terminal. Pass the password as an \
+                        argument, set GALA_PASSWORD, or pipe it with --password-stdin."
+                    );
+                    drop(_cookie_saver);
+                    std::process::exit(1);
+                }
                 None => {
                     rpassword::prompt_password("Password: ").expect("Failed to read from stdin")
                 }
             };
 
-            match auth::login(&client, &email, &password).await {
-                Ok(Some(LoginResult { message, status })) => {
-                    if status != "success" {
-                        println!("Login failed: {}", message);
-                        return;
+            if password.trim().is_empty() {
+                println!("Password can't be empty");
+                drop(_cookie_saver);
+                std::process::exit(1);
+            }
+
+            let mut result = auth::login(&client, &email, &password, code.as_deref()).await;
+            if code.is_none() {
+                if let Ok(Some(login)) = &result {
+                    if login.needs_security_code() {
+                        // Prompting without anyone to answer would hang forever, e.g. in CI
+                        if password_stdin || !std::io::stdin().is_terminal() {
+                            println!(
+                                "Your account needs the security code indieGala emailed you: {}. \
+                                Pass it with --code or GALA_2FA_CODE.",
+                                login.message
+                            );
+                            drop(_cookie_saver);
+                            std::process::exit(1);
+                        }
+                        println!("{}", login.message);
+                        print!("Security code: ");
+                        let _ = std::io::stdout().flush();
+                        let mut code = String::new();
+                        std::io::stdin()
+                            .read_line(&mut code)
+                            .expect("Failed to read from stdin");
+                        result = auth::login(&client, &email, &password, Some(code.trim())).await;
                     }
+                }
+            }
 
-                    match auth::sync(&client).await {
-                        Ok(Some(result)) => save_user_info(&result),
-                        Ok(None) => {
-                            println!("Failed to sync: your authentication is invalid.");
+            match result {
+                Ok(Some(login)) => {
+                    if login.needs_security_code() {
+                        println!("Wrong or expired security code: {}", login.message);
+                        drop(_cookie_saver);
+                        std::process::exit(1);
+                    }
+                    if !login.is_success() {
+                        println!(
+                            "Login failed, check your email and password: {}",
+                            login.message
+                        );
+                        drop(_cookie_saver);
+                        std::process::exit(1);
+                    }
+
+                    if save_to_keyring || remember {
+                        match credentials::save_password(&email, &password).await {
+                            Ok(()) => status!("Saved password to keyring"),
+                            Err(err) => println!("Failed to save password to keyring: {err}"),
                         }
-                        Err(err) => println!("Failed to sync: {err:#?}"),
+                    }
+                    // Logging in without --remember forgets a previously remembered account
+                    let session = SessionConfig {
+                        remembered_email: remember.then(|| email.to_owned()),
+                    };
+                    if let Err(err) = session.store() {
+                        println!("Failed to save session config: {err}");
+                    }
+
+                    if args.no_sync {
+                        status!("Logged in, the library will be synced by the next command");
+                        return;
+                    }
+
+                    match auth::sync(&client, true).await {
+                        Ok(result) => save_user_info(&result),
+                        Err(err) => println!("Failed to sync: {err}"),
                     };
                 }
                 Ok(None) => {
                     println!("Failed to parse login response");
+                    drop(_cookie_saver);
+                    std::process::exit(1);
+                }
+                Err(err) => {
+                    println!("Failed to login: {err:#?}");
+                    drop(_cookie_saver);
+                    std::process::exit(1);
                 }
-                Err(err) => println!("Failed to login: {err:#?}"),
             }
         }
-        Commands::Logout => {
-            UserConfig::clear().expect("Error clearing user config");
-            LibraryConfig::clear().expect("Error clearing library");
+        Commands::LoginToken { cookie } => {
+            let url = reqwest::Url::parse(*BASE_URL).expect("Invalid base URL");
+            {
+                let mut cookie_store = cookie_store.lock().unwrap();
+                for pair in cookie.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+                    // Pasted cookies don't carry their attributes, so they're given an expiry
+                    // (without one they're never saved) and shared with every indieGala domain
+                    let cookie = format!("{pair}; Domain=indiegala.com; Path=/; Max-Age=2592000");
+                    if let Err(err) = cookie_store.parse(&cookie, &url) {
+                        println!("Ignoring invalid cookie {pair:?}: {err}");
+                    }
+                }
+            }
+
+            match auth::sync(&client, true).await {
+                Ok(result) => {
+                    save_user_info(&result);
+                    println!("Logged in with the session cookie");
+                }
+                Err(FreeCarnivalError::Auth) => {
+                    println!("Failed to login: the session cookie is invalid or expired.");
+                    cookie_store.lock().unwrap().clear();
+                }
+                Err(err) => println!("Failed to sync: {err}"),
+            };
+        }
+        Commands::Logout { reset_session } => {
+            if !reset_session {
+                let email = UserConfig::load()
+                    .ok()
+                    .and_then(|config| config.user_info)
+                    .and_then(|user_info| user_info.email);
+                if let Some(email) = email {
+                    if let Err(err) = credentials::delete_password(&email).await {
+                        println!("Failed to remove password from keyring: {err}");
+                    }
+                }
+                UserConfig::clear().expect("Error clearing user config");
+                LibraryConfig::clear().expect("Error clearing library");
+                SessionConfig::clear().expect("Error clearing session config");
+            }
             cookie_store.lock().unwrap().clear();
         }
-        Commands::Library => {
-            let library = LibraryConfig::load().expect("Failed to load library");
+        Commands::Library { format } => {
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            if output::is_json() {
+                let json = serde_json::to_string(&library.collection)
+                    .expect("Failed to serialize library");
+                println!("{json}");
+                return;
+            }
             for product in library.collection {
+                match &format {
+                    Some(template) => println!("{}", product.render(template)),
+                    None => println!("{}", product),
+                }
+            }
+        }
+        Commands::Search {
+            query,
+            installed,
+            owned_only,
+        } => {
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            let installed_games = if installed || owned_only {
+                load_or_exit!(InstalledConfig, _cookie_saver)
+            } else {
+                InstalledConfig::new()
+            };
+            let query = query.to_lowercase();
+            let matches: Vec<_> = library
+                .collection
+                .iter()
+                .filter(|product| {
+                    product.name.to_lowercase().contains(&query)
+                        || product.slugged_name.contains(&query)
+                        || product.namespace.to_lowercase().contains(&query)
+                })
+                .filter(|product| {
+                    let is_installed = installed_games.contains_key(&product.slugged_name);
+                    (!installed || is_installed) && (!owned_only || !is_installed)
+                })
+                .collect();
+            if matches.is_empty() {
+                println!("No games in your library match {query:?}");
+            }
+            for product in matches {
                 println!("{}", product);
             }
         }
+        Commands::Refresh => {
+            let before = LibraryConfig::load()
+                .map(|library| library.collection.len())
+                .unwrap_or_default();
+            LibraryConfig::clear().expect("Error clearing library");
+
+            status!("Syncing library...");
+            match auth::sync_relogging_in(&client, true).await {
+                Ok(result) => {
+                    save_user_info(&result);
+                    println!(
+                        "Library refreshed: {before} products before, {} now.",
+                        result.library_config.collection.len()
+                    );
+                }
+                Err(err) => println!("Failed to sync: {err}"),
+            };
+        }
         Commands::Install {
-            slug,
+            slugs,
             version,
             path,
             base_path,
+            dir_name,
             os,
+            manifest_only,
+            manifest,
+            staging_dir,
+            max_size,
+            force,
+            resume,
+            no_space_check,
+            download_only,
+            from_local,
             install_opts,
         } => {
-            let mut installed = InstalledConfig::load().expect("Failed to load installed");
-            if installed.contains_key(&slug) && !install_opts.info {
-                println!("{slug} already installed.");
-                return;
+            // These pick the build or location of a single game
+            if slugs.len() > 1 {
+                let single_game_options = [
+                    ("--version", version.is_some()),
+                    ("--path", path.is_some()),
+                    ("--dir-name", dir_name.is_some()),
+                    ("--manifest-only", manifest_only.is_some()),
+                    ("--manifest", manifest.is_some()),
+                    ("--download-only", download_only.is_some()),
+                    ("--from-local", from_local.is_some()),
+                ];
+                if let Some((option, _)) = single_game_options.iter().find(|(_, set)| *set) {
+                    println!("{option} can only be used when installing a single game");
+                    return;
+                }
             }
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            // Shared by every game in this run, so `--max-concurrency` is a global limit
+            let download_budget = Arc::new(Semaphore::new(install_opts.max_concurrency.initial()));
 
-            let install_path = match (path, base_path) {
-                (Some(path), _) => path,
-                (None, Some(base_path)) => base_path.join(&slug),
-                (None, None) => DEFAULT_BASE_INSTALL_PATH.join(&slug),
-            };
+            for slug in slugs {
+                if installed.contains_key(&slug)
+                    && !install_opts.info
+                    && manifest_only.is_none()
+                    && download_only.is_none()
+                {
+                    println!("{slug} already installed.");
+                    continue;
+                }
 
-            let library = LibraryConfig::load().expect("Failed to load library");
-            let selected_version = match (
-                version,
-                library.collection.iter().find(|p| p.slugged_name == slug),
-            ) {
-                (Some(version), Some(product)) => {
-                    match product.version.iter().find(|v| {
-                        v.version == version
-                            && match &os {
-                                Some(target) => v.os == *target,
-                                None => true,
+                let dir_name = dir_name.as_ref().unwrap_or(&slug);
+                if !matches!(
+                    std::path::Path::new(dir_name)
+                        .components()
+                        .collect::<Vec<_>>()[..],
+                    [std::path::Component::Normal(_)]
+                ) {
+                    println!("{dir_name} is not a valid directory name");
+                    continue;
+                }
+                let selected_version = match (
+                    &version,
+                    library.collection.iter().find(|p| p.slugged_name == slug),
+                ) {
+                    (Some(version), Some(product)) => {
+                        // Without --os, a build for this OS is preferred when the version was
+                        // published for several
+                        let native = BuildOs::native();
+                        let target = os.as_ref().unwrap_or(&native);
+                        let matching = product.version.iter().filter(|v| &v.version == version);
+                        let found = match matching.clone().find(|v| v.os == *target) {
+                            Some(found) => Some(found),
+                            None if os.is_none() => matching.clone().next(),
+                            None => None,
+                        };
+                        match found {
+                            Some(version) => Some(version),
+                            None => {
+                                match product.missing_build_error(os.as_ref()) {
+                                    err @ FreeCarnivalError::NoBuildForOs { .. }
+                                        if os.is_some() =>
+                                    {
+                                        println!("{err}")
+                                    }
+                                    _ => {
+                                        println!("Can't find or install build {version} for {slug}")
+                                    }
+                                }
+                                continue;
                             }
-                    }) {
-                        Some(version) => Some(version),
+                        }
+                    }
+                    (_, None) => {
+                        println!("{slug} is not in your library");
+                        continue;
+                    }
+                    _ => None,
+                };
+                if let Some(out) = &manifest_only {
+                    let product = library
+                        .collection
+                        .iter()
+                        .find(|p| p.slugged_name == slug)
+                        .expect("Product was already found in library");
+                    let build_version = match selected_version
+                        .or_else(|| product.get_latest_version(os.as_ref()))
+                    {
+                        Some(build_version) => build_version,
                         None => {
-                            println!("Can't find or install build {version} for {slug}");
-                            return;
+                            println!("{}", product.missing_build_error(os.as_ref()));
+                            continue;
                         }
+                    };
+                    match utils::dump_manifest(&client, product, build_version, out).await {
+                        Ok(info) => println!("{}", info),
+                        Err(err) => println!("Failed to fetch manifest for {}: {}", &slug, err),
                     }
+                    continue;
                 }
-                (_, None) => {
-                    println!("{slug} is not in your library");
+                let install_path = match (&path, &base_path) {
+                    (Some(path), _) => path.to_owned(),
+                    (None, Some(base_path)) => base_path.join(dir_name),
+                    (None, None) if settings.base_paths.is_empty() => {
+                        DEFAULT_BASE_INSTALL_PATH.join(dir_name)
+                    }
+                    (None, None) => {
+                        let product = library
+                            .collection
+                            .iter()
+                            .find(|p| p.slugged_name == slug)
+                            .expect("Product was already found in library");
+                        let build_version = match selected_version
+                            .or_else(|| product.get_latest_version(os.as_ref()))
+                        {
+                            Some(build_version) => build_version,
+                            None => {
+                                println!("{}", product.missing_build_error(os.as_ref()));
+                                continue;
+                            }
+                        };
+                        match utils::select_base_path(
+                            &client,
+                            product,
+                            build_version,
+                            &settings.base_paths,
+                            !no_space_check,
+                        )
+                        .await
+                        {
+                            Ok(base_path) => base_path.join(dir_name),
+                            Err(err) => {
+                                println!("Failed to pick a base path for {slug}: {}", err);
+                                continue;
+                            }
+                        }
+                    }
+                };
+                let staging_path = match (
+                    &download_only,
+                    staging_dir.as_ref().or(settings.staging_dir.as_ref()),
+                ) {
+                    (Some(download_dir), _) => download_dir.to_owned(),
+                    (None, Some(staging_dir)) => staging_dir.join(&slug),
+                    (None, None) => install_path.with_file_name(format!(
+                        ".{}.staging",
+                        install_path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| slug.to_owned())
+                    )),
+                };
+                let result = match &from_local {
+                    Some(local_dir) => {
+                        utils::install_from_local(&slug, local_dir, &install_path).await
+                    }
+                    None => {
+                        utils::install(
+                            client.clone(),
+                            &slug,
+                            &install_path,
+                            &staging_path,
+                            install_opts.clone(),
+                            download_budget.clone(),
+                            selected_version,
+                            os.clone(),
+                            max_size,
+                            force,
+                            resume,
+                            !no_space_check,
+                            manifest.as_deref(),
+                            download_only.is_some(),
+                        )
+                        .await
+                    }
+                };
+                match result {
+                    Ok((info, Some(install_info))) => {
+                        print_output(&info);
+
+                        events::emit(
+                            &settings.event_hooks,
+                            Event::InstallComplete {
+                                slug: &slug,
+                                version: &install_info.version,
+                                install_path: &install_info.install_path,
+                            },
+                        )
+                        .await;
+                        installed.insert(slug, install_info);
+                        installed
+                            .store()
+                            .expect("Failed to update installed config");
+                    }
+                    Ok((info, None)) => {
+                        print_output(&info);
+                    }
+                    Err(err) => {
+                        println!("Failed to install {}: {}", &slug, err);
+                    }
+                };
+            }
+        }
+        Commands::Complete { slug, install_opts } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            let install_info = match installed.get(&slug) {
+                Some(info) => info.clone(),
+                None => {
+                    println!("{slug} is not installed.");
                     return;
                 }
-                _ => None,
             };
-            match utils::install(
+
+            let download_budget = Arc::new(Semaphore::new(install_opts.max_concurrency.initial()));
+            match utils::complete(
                 client.clone(),
+                &library,
                 &slug,
-                &install_path,
                 install_opts,
-                selected_version,
-                os,
+                download_budget,
+                &install_info,
             )
             .await
             {
-                Ok(Ok((info, Some(install_info)))) => {
-                    println!("{}", info);
-
-                    installed.insert(slug, install_info);
+                Ok((info, Some(completed_info))) => {
+                    print_output(&info);
+                    installed.insert(slug, completed_info);
                     installed
                         .store()
                         .expect("Failed to update installed config");
                 }
-                Ok(Ok((info, None))) => {
-                    println!("{}", info);
-                }
-                Ok(Err(err)) => {
-                    println!("Failed to install {}: {:?}", &slug, err);
+                Ok((info, None)) => {
+                    print_output(&info);
                 }
                 Err(err) => {
-                    println!("Failed to install {}: {:?}", &slug, err);
+                    println!("Failed to complete {slug}: {}", err);
                 }
             };
         }
-        Commands::Uninstall { slug, keep } => {
-            let mut installed = InstalledConfig::load().expect("Failed to load installed");
-            let install_info = match installed.remove(&slug) {
-                Some(info) => info,
-                None => {
-                    println!("{slug} is not installed.");
-                    return;
-                }
-            };
-
-            let folder_removed = if keep {
-                false
+        Commands::Uninstall {
+            slug,
+            tag,
+            keep,
+            keep_saves,
+            dry_run,
+        } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let protected = if keep_saves {
+                Some(ProtectedPaths::new(&settings.protected_paths))
             } else {
-                match utils::uninstall(&install_info.install_path).await {
-                    Ok(()) => true,
-                    Err(err) => {
-                        println!("Failed to uninstall {slug}: {:?}", err);
-                        false
+                None
+            };
+            for slug in select_slugs(&installed, slug, tag.as_ref()) {
+                let install_path = match installed.get(&slug) {
+                    Some(info) => info.install_path.to_owned(),
+                    None => {
+                        println!("{slug} is not installed.");
+                        continue;
                     }
+                };
+                if dry_run {
+                    if let Err(err) =
+                        utils::uninstall(&install_path, protected.as_ref(), true).await
+                    {
+                        println!("Failed to list the files of {slug}: {:?}", err);
+                        continue;
+                    }
+                    println!(
+                        "{slug} would be uninstalled. {}",
+                        if keep_saves {
+                            "Files matching the protected paths would be kept."
+                        } else {
+                            "Saves would not be kept, use --keep-saves to keep them."
+                        }
+                    );
+                    continue;
                 }
-            };
-            installed
-                .store()
-                .expect("Failed to update installed config");
-            println!(
-                "{slug} uninstalled successfuly. {} was {}.",
-                install_info.install_path.display(),
-                if folder_removed {
-                    "removed"
-                } else {
-                    "not removed"
+                if !keep
+                    && !output::confirm(&format!(
+                        "Uninstall {slug} and delete {}?",
+                        install_path.display()
+                    ))
+                {
+                    println!("Skipping {slug}.");
+                    continue;
                 }
-            );
+                let folder_removed = if keep {
+                    false
+                } else {
+                    match utils::uninstall(&install_path, protected.as_ref(), false).await {
+                        Ok(()) => true,
+                        // The game stays registered so running uninstall again finishes the job
+                        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
+                            println!(
+                                "Uninstall of {slug} was cancelled. It's still installed, and \
+                                some of its files may have been deleted."
+                            );
+                            break;
+                        }
+                        Err(err) => {
+                            println!("Failed to uninstall {slug}: {:?}", err);
+                            false
+                        }
+                    }
+                };
+                let install_info = installed.remove(&slug).unwrap();
+                installed
+                    .store()
+                    .expect("Failed to update installed config");
+                println!(
+                    "{slug} uninstalled successfuly. {} was {}.",
+                    install_info.install_path.display(),
+                    match (folder_removed, keep_saves) {
+                        (true, true) => "removed, except for protected files",
+                        (true, false) => "removed",
+                        (false, _) => "not removed",
+                    }
+                );
+            }
         }
-        Commands::ListUpdates => {
-            let installed = InstalledConfig::load().expect("Failed to load installed");
-            let library = LibraryConfig::load().expect("Failed to load library");
+        Commands::ListUpdates { new_only } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
 
-            match utils::check_updates(library, installed).await {
-                Ok(available_updates) => {
-                    if available_updates.is_empty() {
-                        println!("No available updates");
+            match utils::check_updates(library, installed.clone()).await {
+                Ok(mut available_updates) => {
+                    if new_only {
+                        available_updates.retain(|slug, latest_version| {
+                            installed[slug].notified_version.as_ref() != Some(latest_version)
+                        });
+                        for (slug, latest_version) in &available_updates {
+                            if let Some(info) = installed.get_mut(slug) {
+                                info.notified_version = Some(latest_version.to_owned());
+                            }
+                        }
+                        installed
+                            .store()
+                            .expect("Failed to update installed config");
+                    }
+                    if available_updates.is_empty() && !output::is_json() {
+                        println!("No {} updates", if new_only { "new" } else { "available" });
                         return;
                     }
 
-                    for (slug, latest_version) in available_updates {
-                        println!("{slug} has an update -> {latest_version}");
+                    let updates: Vec<AvailableUpdate> = available_updates
+                        .into_iter()
+                        .map(|(slug, latest_version)| AvailableUpdate {
+                            current_version: installed[&slug].version.to_owned(),
+                            slug,
+                            latest_version,
+                        })
+                        .collect();
+                    if output::is_json() {
+                        let json =
+                            serde_json::to_string(&updates).expect("Failed to serialize updates");
+                        println!("{json}");
+                    }
+                    for update in updates {
+                        if !output::is_json() {
+                            println!("{update}");
+                        }
+                        events::emit(
+                            &settings.event_hooks,
+                            Event::UpdateAvailable {
+                                slug: &update.slug,
+                                installed_version: &update.current_version,
+                                version: &update.latest_version,
+                            },
+                        )
+                        .await;
                     }
                 }
                 Err(err) => {
@@ -213,75 +750,221 @@ async fn main() {
         }
         Commands::Update {
             slug,
+            all: _,
+            tag,
             version,
+            force,
+            verify_repair,
+            backup,
             install_opts,
         } => {
-            let mut installed = InstalledConfig::load().expect("Failed to load installed");
-            let install_info = match installed.remove(&slug) {
-                Some(info) => info,
-                None => {
-                    println!("{slug} is not installed.");
-                    return;
-                }
-            };
-            let library = LibraryConfig::load().expect("Failed to load library");
-            let selected_version = match (
-                version,
-                library.collection.iter().find(|p| p.slugged_name == slug),
-            ) {
-                (Some(version), Some(product)) => {
-                    match product.version.iter().find(|v| v.version == version) {
-                        Some(version) => Some(version),
-                        None => {
-                            println!("Couldn't find build {version} for {slug}");
-                            return;
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            let slugs = select_slugs(&installed, slug, tag.as_ref());
+            // Shared by every game in this run, so `--max-concurrency` is a global limit
+            let download_budget = Arc::new(Semaphore::new(install_opts.max_concurrency.initial()));
+
+            for slug in slugs {
+                let install_info = match installed.get(&slug) {
+                    Some(info) => info.clone(),
+                    None => {
+                        println!("{slug} is not installed.");
+                        continue;
+                    }
+                };
+                let selected_version = match (
+                    &version,
+                    library.collection.iter().find(|p| p.slugged_name == slug),
+                ) {
+                    (Some(version), Some(product)) => {
+                        // The same version string can be shared by builds of different OSes,
+                        // so only consider builds for the installed OS
+                        match product
+                            .version
+                            .iter()
+                            .find(|v| &v.version == version && v.os == install_info.os)
+                        {
+                            Some(version) => Some(version),
+                            None => {
+                                println!(
+                                    "Couldn't find {} build {version} for {slug}",
+                                    install_info.os
+                                );
+                                continue;
+                            }
                         }
                     }
-                }
-                (_, None) => {
-                    println!("{slug} is not in your library");
-                    return;
-                }
-                _ => None,
-            };
+                    (_, None) => {
+                        println!("{slug} is not in your library");
+                        continue;
+                    }
+                    _ => None,
+                };
 
-            match utils::update(
-                client.clone(),
-                &library,
-                &slug,
-                install_opts,
-                &install_info,
-                selected_version,
-            )
-            .await
-            {
-                Ok((info, Some(install_info))) => {
-                    println!("{}", info);
-                    installed.insert(slug, install_info);
-                    installed
-                        .store()
-                        .expect("Failed to update installed config");
-                }
-                Ok((info, None)) => {
-                    println!("{}", info);
-                }
-                Err(err) => {
-                    println!("Failed to update {slug}: {:?}", err);
-                }
-            };
+                match utils::update(
+                    client.clone(),
+                    &library,
+                    &slug,
+                    install_opts.clone(),
+                    download_budget.clone(),
+                    &install_info,
+                    selected_version,
+                    force,
+                    verify_repair,
+                    backup,
+                )
+                .await
+                {
+                    Ok((info, Some(updated_info))) => {
+                        print_output(&info);
+                        events::emit(
+                            &settings.event_hooks,
+                            Event::UpdateComplete {
+                                slug: &slug,
+                                from_version: &install_info.version,
+                                to_version: &updated_info.version,
+                            },
+                        )
+                        .await;
+                        installed.insert(slug, updated_info);
+                        installed
+                            .store()
+                            .expect("Failed to update installed config");
+                    }
+                    Ok((info, None)) => {
+                        print_output(&info);
+                    }
+                    // The installed config keeps the previous build, the files are only rolled back
+                    // with --backup
+                    Err(err) => {
+                        println!("Failed to update {slug}: {}", err);
+                    }
+                };
+            }
         }
         Commands::Launch {
             slug,
             #[cfg(not(target_os = "windows"))]
             wine,
             #[cfg(not(target_os = "windows"))]
+            runner,
+            #[cfg(not(target_os = "windows"))]
             wine_prefix,
             #[cfg(not(target_os = "windows"))]
             no_wine,
             wrapper,
+            #[cfg(target_os = "linux")]
+            gamescope,
+            #[cfg(target_os = "linux")]
+            gamescope_args,
+            gpu,
+            #[cfg(target_os = "linux")]
+            remember,
+            #[cfg(not(target_os = "windows"))]
+            winetricks,
+            check_deps,
+            print_env,
+            hooks,
+            game_args,
         } => {
-            let installed = InstalledConfig::load().expect("Failed to load installed");
-            let library = LibraryConfig::load().expect("Failed to load library");
+            let installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            #[cfg(not(target_os = "windows"))]
+            let mut installed = installed;
+            #[cfg(not(target_os = "windows"))]
+            let wine = match runner {
+                Some(name) => match helpers::discover_runners()
+                    .into_iter()
+                    .find(|r| r.name == name)
+                {
+                    Some(runner) => Some(runner.path),
+                    None => {
+                        println!(
+                            "Couldn't find a runner called {name}. Run `runners` to list them."
+                        );
+                        return;
+                    }
+                },
+                None => wine,
+            };
+            // Flags that weren't given fall back to the launch options saved with `config`
+            let saved = installed
+                .get(&slug)
+                .map(|info| info.launch_options.clone())
+                .unwrap_or_default();
+            #[cfg(not(target_os = "windows"))]
+            let wine = wine.or(saved.wine);
+            #[cfg(not(target_os = "windows"))]
+            let wine_prefix = wine_prefix.or(saved.wine_prefix);
+            let wrapper = wrapper.or(saved.wrapper);
+            let game_args = if game_args.is_empty() {
+                saved.args
+            } else {
+                game_args
+            };
+            let hooks = if hooks.no_hooks {
+                cli::HookOpts {
+                    pre_launch: None,
+                    post_exit: None,
+                    ..hooks
+                }
+            } else {
+                cli::HookOpts {
+                    pre_launch: hooks.pre_launch.or(saved.pre_launch),
+                    post_exit: hooks.post_exit.or(saved.post_exit),
+                    ..hooks
+                }
+            };
+            #[cfg(target_os = "linux")]
+            let gamescope = match (gamescope, gamescope_args) {
+                (_, Some(args)) => Some(args),
+                (true, None) => Some(String::new()),
+                (false, None) => installed
+                    .get(&slug)
+                    .and_then(|info| info.launch_options.gamescope.clone()),
+            };
+            #[cfg(target_os = "linux")]
+            let gpu = gpu.or_else(|| {
+                installed
+                    .get(&slug)
+                    .and_then(|info| info.launch_options.gpu.clone())
+            });
+            #[cfg(not(target_os = "linux"))]
+            if gpu.is_some() {
+                println!("--gpu is only supported on Linux, ignoring it");
+            }
+            #[cfg(target_os = "linux")]
+            if remember {
+                if let Some(info) = installed.get_mut(&slug) {
+                    info.launch_options.gamescope = gamescope.clone();
+                    info.launch_options.gpu = gpu.clone();
+                    installed
+                        .store()
+                        .expect("Failed to update installed config");
+                }
+            }
+            // Only run the verbs that weren't already set up for this game
+            #[cfg(not(target_os = "windows"))]
+            if let Some(info) = installed.get_mut(&slug) {
+                let missing_verbs: Vec<String> = winetricks
+                    .into_iter()
+                    .filter(|verb| !info.winetricks_verbs.contains(verb))
+                    .collect();
+                if !missing_verbs.is_empty() && info.os == BuildOs::Windows && !no_wine {
+                    match utils::winetricks(&missing_verbs, wine.as_ref(), wine_prefix.as_ref())
+                        .await
+                    {
+                        Ok(true) => {
+                            info.winetricks_verbs.extend(missing_verbs);
+                            installed
+                                .store()
+                                .expect("Failed to update installed config");
+                        }
+                        Ok(false) => {}
+                        Err(err) => println!("Failed to run winetricks: {:?}", err),
+                    }
+                }
+            }
             let install_info = match installed.get(&slug) {
                 Some(info) => info,
                 None => {
@@ -307,12 +990,31 @@ async fn main() {
                 #[cfg(not(target_os = "windows"))]
                 wine_prefix,
                 wrapper,
+                #[cfg(target_os = "linux")]
+                gamescope.as_deref(),
+                #[cfg(target_os = "linux")]
+                gpu.as_deref(),
+                &hooks,
+                check_deps,
+                print_env,
+                &game_args,
             )
             .await
             {
                 Ok(Some(status)) => {
                     println!("Process exited with: {}", status);
+                    if !hooks.no_hooks {
+                        events::emit(
+                            &settings.event_hooks,
+                            Event::GameExited {
+                                slug: &slug,
+                                exit_code: status.code(),
+                            },
+                        )
+                        .await;
+                    }
                 }
+                Ok(None) if check_deps || print_env => {}
                 Ok(None) => {
                     println!("Failed to launch {slug}");
                 }
@@ -321,8 +1023,72 @@ async fn main() {
                 }
             };
         }
-        Commands::Info { slug } => {
-            let library = LibraryConfig::load().expect("Failed to load library");
+        Commands::Info { slugs } => {
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            let installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let combined = slugs.len() > 1;
+            let mut entries = vec![];
+            for slug in slugs {
+                let product = match library.collection.iter().find(|p| p.slugged_name == slug) {
+                    Some(p) => p,
+                    None => {
+                        let error = format!("{slug} is not in your library");
+                        if output::is_json() && combined {
+                            entries.push(InfoEntry::Missing { slug, error });
+                        } else {
+                            println!("{error}");
+                        }
+                        continue;
+                    }
+                };
+                let install_info = installed.get(&slug);
+
+                // Build sizes take a request each, so they're only fetched for the JSON output
+                let mut versions = vec![];
+                for version in &product.version {
+                    let size_in_bytes = if output::is_json() {
+                        utils::build_size(&client, product, version).await.ok()
+                    } else {
+                        None
+                    };
+                    versions.push(VersionEntry {
+                        version: version.version.to_owned(),
+                        os: version.os.to_owned(),
+                        date: version.date,
+                        size_in_bytes,
+                        installed: install_info.is_some_and(|info| {
+                            info.version == version.version && info.os == version.os
+                        }),
+                    });
+                }
+
+                let game_info = GameInfo {
+                    product,
+                    versions,
+                    installed: install_info.map(|info| InstalledGame {
+                        version: &info.version,
+                        os: &info.os,
+                        install_path: &info.install_path,
+                    }),
+                };
+                if output::is_json() && combined {
+                    entries.push(InfoEntry::Found(game_info));
+                } else {
+                    if combined {
+                        println!("{product}");
+                    }
+                    print_output(&game_info);
+                }
+            }
+
+            if output::is_json() && combined {
+                let json = serde_json::to_string(&entries).expect("Failed to serialize info");
+                println!("{json}");
+            }
+        }
+        Commands::Versions { slug, sort, size } => {
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            let installed = load_or_exit!(InstalledConfig, _cookie_saver);
             let product = match library.collection.iter().find(|p| p.slugged_name == slug) {
                 Some(p) => p,
                 None => {
@@ -330,22 +1096,174 @@ async fn main() {
                     return;
                 }
             };
-
-            let installed = InstalledConfig::load().expect("Failed to load installed");
             let install_info = installed.get(&slug);
 
-            println!(
-                "Available Versions:\n{}",
-                product
-                    .version
-                    .iter()
-                    .map(|v| format!("\n{}", v))
-                    .collect::<Vec<String>>()
-                    .join("\n")
-            );
+            let mut versions: Vec<&ProductVersion> = product.version.iter().collect();
+            match sort {
+                VersionSort::Date => versions.sort_by_key(|v| std::cmp::Reverse(v.date)),
+                VersionSort::Version => {
+                    versions.sort_by(|a, b| helpers::compare_versions(&b.version, &a.version))
+                }
+                VersionSort::Os => versions.sort_by(|a, b| {
+                    a.os.to_string()
+                        .cmp(&b.os.to_string())
+                        .then(b.date.cmp(&a.date))
+                }),
+            }
+
+            let mut entries = vec![];
+            for version in versions {
+                let size_in_bytes = if size {
+                    match utils::build_size(&client, product, version).await {
+                        Ok(size) => Some(size),
+                        Err(err) => {
+                            println!("Failed to get size of {}: {}", version.version, err);
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+                let entry = VersionEntry {
+                    version: version.version.to_owned(),
+                    os: version.os.to_owned(),
+                    date: version.date,
+                    size_in_bytes,
+                    installed: install_info.is_some_and(|info| {
+                        info.version == version.version && info.os == version.os
+                    }),
+                };
+                // The JSON output is a single array, printed once every size was fetched
+                if output::is_json() {
+                    entries.push(entry);
+                } else {
+                    print_output(&entry);
+                }
+            }
+
+            if output::is_json() {
+                let json = serde_json::to_string(&entries).expect("Failed to serialize versions");
+                println!("{json}");
+            }
+        }
+        Commands::Verify {
+            slug,
+            all: _,
+            tag,
+            changed,
+            report,
+            jobs,
+        } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            // Shared by every game in this run, so `--jobs` is a global limit
+            let hash_jobs = Arc::new(Semaphore::new(jobs.max(1)));
+            let bars = output::progress_bars();
+            let mut verifications = vec![];
+            for slug in select_slugs(&installed, slug, tag.as_ref()) {
+                let install_info = match installed.get(&slug) {
+                    Some(info) => info.clone(),
+                    None => {
+                        println!("{slug} is not installed.");
+                        continue;
+                    }
+                };
+
+                let changed_only = changed && install_info.last_verified.is_some();
+                if changed && !changed_only {
+                    status!("{slug} was never verified, verifying every file...");
+                }
+                let client = client.clone();
+                let hash_jobs = hash_jobs.clone();
+                let bars = bars.clone();
+                let verification = tokio::spawn({
+                    let slug = slug.to_owned();
+                    async move {
+                        utils::verify(
+                            &client,
+                            &slug,
+                            &install_info,
+                            changed_only,
+                            hash_jobs,
+                            &bars,
+                        )
+                        .await
+                    }
+                });
+                verifications.push((slug, verification));
+            }
+
+            let mut failures = vec![];
+            let mut failed_games = vec![];
+            let total = verifications.len();
+            for (slug, verification) in verifications {
+                let result = verification.await.expect("Verification task panicked");
+                let outcome = VerifyOutcome {
+                    slug: slug.to_owned(),
+                    ok: matches!(&result, Ok(result) if result.failures.is_empty()),
+                    bad_files: result.as_ref().map_or(0, |result| result.failures.len()),
+                    error: result.as_ref().err().map(|err| err.to_string()),
+                    failures: result
+                        .as_ref()
+                        .map_or(vec![], |result| result.failures.clone()),
+                };
+                print_output(&outcome);
+                let passed = match result {
+                    Ok(result) if result.failures.is_empty() => {
+                        let install_info = installed.get_mut(&slug).unwrap();
+                        install_info.last_verified = Some(chrono::Utc::now());
+                        install_info.verified_files = result.snapshot;
+                        installed
+                            .store()
+                            .expect("Failed to update installed config");
+                        true
+                    }
+                    Ok(result) => {
+                        failures.extend(result.failures);
+                        false
+                    }
+                    Err(_) => false,
+                };
+                if !passed {
+                    failed_games.push(slug.to_owned());
+                }
+                events::emit(
+                    &settings.event_hooks,
+                    Event::VerifyComplete {
+                        slug: &slug,
+                        passed,
+                    },
+                )
+                .await;
+            }
+            if total > 1 && !output::is_json() {
+                println!(
+                    "{} of {total} game(s) passed verification.",
+                    total - failed_games.len()
+                );
+                if !failed_games.is_empty() {
+                    println!("Failed: {}", failed_games.join(", "));
+                }
+            }
+
+            if let Some(report) = report {
+                let report = match &args.output_dir {
+                    Some(output_dir) => output_dir.join(report),
+                    None => report,
+                };
+                let json =
+                    serde_json::to_vec_pretty(&failures).expect("Failed to serialize report");
+                if let Err(err) = std::fs::write(&report, json) {
+                    println!("Failed to write report to {}: {}", report.display(), err);
+                }
+            }
+            if !failed_games.is_empty() {
+                // Exiting skips destructors, so the cookies are saved first
+                drop(_cookie_saver);
+                std::process::exit(1);
+            }
         }
-        Commands::Verify { slug } => {
-            let installed = InstalledConfig::load().expect("Failed to load installed");
+        Commands::Repair { slug, install_opts } => {
+            let installed = load_or_exit!(InstalledConfig, _cookie_saver);
             let install_info = match installed.get(&slug) {
                 Some(info) => info,
                 None => {
@@ -353,29 +1271,476 @@ async fn main() {
                     return;
                 }
             };
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            let download_budget = Arc::new(Semaphore::new(install_opts.max_concurrency.initial()));
+            match utils::repair(
+                client.clone(),
+                &library,
+                &slug,
+                install_opts,
+                download_budget,
+                install_info,
+            )
+            .await
+            {
+                Ok(summary) => println!("{summary}"),
+                Err(err) => println!("Failed to repair {slug}: {err}"),
+            }
+        }
+        Commands::Fetch {
+            slug,
+            path,
+            out,
+            version,
+            os,
+            install_opts,
+        } => {
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            let product = match library.collection.iter().find(|p| p.slugged_name == slug) {
+                Some(p) => p,
+                None => {
+                    println!("{slug} is not in your library");
+                    return;
+                }
+            };
+            let build_version = match &version {
+                Some(version) => product.version.iter().find(|v| {
+                    &v.version == version
+                        && match &os {
+                            Some(target) => v.os == *target,
+                            None => true,
+                        }
+                }),
+                None => product.get_latest_version(os.as_ref()),
+            };
+            let build_version = match build_version {
+                Some(v) => v,
+                None => {
+                    // Without --os, a specific version is looked up among every OS's builds
+                    match product.missing_build_error(os.as_ref()) {
+                        err @ FreeCarnivalError::NoBuildForOs { .. }
+                            if os.is_some() || version.is_none() =>
+                        {
+                            println!("{err}")
+                        }
+                        _ => println!("Couldn't find a matching build for {slug}"),
+                    }
+                    return;
+                }
+            };
+
+            let download_budget = Arc::new(Semaphore::new(install_opts.max_concurrency.initial()));
+            match utils::fetch(
+                client.clone(),
+                product,
+                build_version,
+                &path,
+                &out,
+                install_opts,
+                download_budget,
+            )
+            .await
+            {
+                Ok(report) => print_output(&report),
+                Err(err) => println!("Failed to fetch {path}: {}", err),
+            }
+        }
+        Commands::Tag { slug, tags } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let install_info = match installed.get_mut(&slug) {
+                Some(info) => info,
+                None => {
+                    println!("{slug} is not installed.");
+                    return;
+                }
+            };
+
+            for tag in tags {
+                if !install_info.tags.contains(&tag) {
+                    install_info.tags.push(tag);
+                }
+            }
+            println!("{slug} tags: {}", install_info.tags.join(", "));
+            installed
+                .store()
+                .expect("Failed to update installed config");
+        }
+        Commands::Untag { slug, tags } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let install_info = match installed.get_mut(&slug) {
+                Some(info) => info,
+                None => {
+                    println!("{slug} is not installed.");
+                    return;
+                }
+            };
+
+            install_info.tags.retain(|tag| !tags.contains(tag));
+            println!("{slug} tags: {}", install_info.tags.join(", "));
+            installed
+                .store()
+                .expect("Failed to update installed config");
+        }
+        Commands::ListInstalled { sort_by } => {
+            let installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let mut entries = vec![];
+            for (slug, install_info) in installed {
+                let size_in_bytes = match helpers::dir_size(&install_info.install_path).await {
+                    Ok(size) => Some(size),
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(err) => {
+                        println!("Failed to get the size of {slug}: {err}");
+                        None
+                    }
+                };
+                entries.push(InstalledEntry {
+                    slug,
+                    version: install_info.version,
+                    os: install_info.os,
+                    install_path: install_info.install_path,
+                    size_in_bytes,
+                });
+            }
+            if entries.is_empty() && !output::is_json() {
+                println!("No games are installed");
+                return;
+            }
+
+            entries.sort_by(|a, b| a.slug.cmp(&b.slug));
+            match sort_by {
+                InstalledSort::Name => {}
+                InstalledSort::Size => {
+                    entries.sort_by_key(|e| std::cmp::Reverse(e.size_in_bytes));
+                }
+                InstalledSort::Version => {
+                    entries.sort_by(|a, b| helpers::compare_versions(&b.version, &a.version))
+                }
+            }
+            for entry in entries {
+                print_output(&entry);
+            }
+        }
+        Commands::Orphans { prune, keep } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let library = load_or_exit!(LibraryConfig, _cookie_saver);
+            // An empty library is more likely a failed or missing sync than a library with
+            // nothing left in it, and would make every installed game an orphan
+            if library.collection.is_empty() && prune {
+                println!("Your library is empty, run `refresh` before pruning orphans.");
+                drop(_cookie_saver);
+                std::process::exit(1);
+            }
+            let mut orphans: Vec<String> = installed
+                .keys()
+                .filter(|slug| !library.collection.iter().any(|p| &p.slugged_name == *slug))
+                .cloned()
+                .collect();
+            orphans.sort();
+            if orphans.is_empty() {
+                println!("Every installed game is in your library");
+                return;
+            }
+
+            for slug in orphans {
+                let install_path = installed[&slug].install_path.to_owned();
+                if !prune {
+                    println!("{slug} ({})", install_path.display());
+                    continue;
+                }
+                if !keep
+                    && !output::confirm(&format!(
+                        "Uninstall {slug} and delete {}?",
+                        install_path.display()
+                    ))
+                {
+                    println!("Skipping {slug}.");
+                    continue;
+                }
+                if !keep {
+                    if let Err(err) = utils::uninstall(&install_path, None, false).await {
+                        println!("Failed to delete {}: {:?}", install_path.display(), err);
+                        continue;
+                    }
+                }
+                installed.remove(&slug);
+                installed
+                    .store()
+                    .expect("Failed to update installed config");
+                println!("Removed {slug}");
+            }
+        }
+        Commands::Config {
+            slug,
+            #[cfg(not(target_os = "windows"))]
+            wine,
+            #[cfg(not(target_os = "windows"))]
+            wine_prefix,
+            wrapper,
+            args,
+            env,
+            pre_launch,
+            post_exit,
+            reset,
+        } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let install_info = match installed.get_mut(&slug) {
+                Some(info) => info,
+                None => {
+                    println!("{slug} is not installed.");
+                    return;
+                }
+            };
+            let options = &mut install_info.launch_options;
+            if reset {
+                *options = Default::default();
+            }
+            #[cfg(not(target_os = "windows"))]
+            {
+                options.wine = wine.or(options.wine.take());
+                options.wine_prefix = wine_prefix.or(options.wine_prefix.take());
+            }
+            options.wrapper = wrapper.or(options.wrapper.take());
+            if !args.is_empty() {
+                options.args = args;
+            }
+            options.env.extend(env);
+            options.pre_launch = pre_launch.or(options.pre_launch.take());
+            options.post_exit = post_exit.or(options.post_exit.take());
+            println!("{options}");
+            installed
+                .store()
+                .expect("Failed to update installed config");
+        }
+        Commands::Move {
+            slug,
+            new_path,
+            force,
+        } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let install_info = match installed.get_mut(&slug) {
+                Some(info) => info,
+                None => {
+                    println!("{slug} is not installed.");
+                    return;
+                }
+            };
+            let new_path = match std::path::absolute(&new_path) {
+                Ok(path) => path,
+                Err(err) => {
+                    println!("Can't use {}: {err}", new_path.display());
+                    return;
+                }
+            };
 
-            match utils::verify(&slug, install_info).await {
-                Ok(true) => {
-                    println!("{slug} passed verification.");
+            status!("Moving {slug} to {}...", new_path.display());
+            // The installed config is only updated once every file was moved
+            match utils::move_install(&install_info.install_path, &new_path, force).await {
+                Ok(()) => {
+                    install_info.install_path = new_path;
+                    println!(
+                        "{slug} is now installed in {}",
+                        install_info.install_path.display()
+                    );
+                    installed
+                        .store()
+                        .expect("Failed to update installed config");
                 }
-                Ok(false) => {
-                    println!("{slug} is corrupted. Please reinstall.");
+                Err(err) => println!("Failed to move {slug}: {err}"),
+            }
+        }
+        Commands::Relink { slug, new_path } => {
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let install_info = match installed.get_mut(&slug) {
+                Some(info) => info,
+                None => {
+                    println!("{slug} is not installed.");
+                    return;
                 }
+            };
+            let new_path = match new_path.canonicalize() {
+                Ok(path) => path,
                 Err(err) => {
-                    println!("Failed to verify files: {}", err);
+                    println!("Can't use {}: {err}", new_path.display());
+                    return;
+                }
+            };
+
+            let mut relinked = install_info.clone();
+            relinked.install_path = new_path;
+            // Moving files usually keeps their modification time, so files that were already
+            // verified are only checked for their size
+            let changed_only = relinked.last_verified.is_some();
+            match utils::verify(
+                &client,
+                &slug,
+                &relinked,
+                changed_only,
+                Arc::new(Semaphore::new(*constants::DEFAULT_VERIFY_JOBS)),
+                &output::progress_bars(),
+            )
+            .await
+            {
+                Ok(result) if result.failures.is_empty() => {
+                    relinked.last_verified = Some(chrono::Utc::now());
+                    relinked.verified_files = result.snapshot;
+                    println!(
+                        "{slug} is now installed in {}",
+                        relinked.install_path.display()
+                    );
+                    *install_info = relinked;
+                    installed
+                        .store()
+                        .expect("Failed to update installed config");
+                }
+                Ok(result) => {
+                    for failure in &result.failures {
+                        println!("{failure}");
+                    }
+                    println!(
+                        "{} doesn't contain {slug} ({} bad file(s)), it wasn't relinked.",
+                        relinked.install_path.display(),
+                        result.failures.len()
+                    );
+                }
+                Err(err) => println!("Failed to verify files: {}", err),
+            }
+        }
+        Commands::Export { path } => {
+            let installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            // Sorted, so exports of the same games are identical
+            let sorted: std::collections::BTreeMap<_, _> = installed.iter().collect();
+            let json = serde_json::to_vec_pretty(&sorted).expect("Failed to serialize installed");
+            match std::fs::write(&path, json) {
+                Ok(()) => println!(
+                    "Exported {} installed game(s) to {}",
+                    installed.len(),
+                    path.display()
+                ),
+                Err(err) => println!("Failed to write {}: {err}", path.display()),
+            }
+        }
+        Commands::Import {
+            path,
+            verify,
+            force,
+        } => {
+            let imported = match std::fs::read(&path).map(|json| {
+                serde_json::from_slice::<InstalledConfig>(&json).map_err(std::io::Error::other)
+            }) {
+                Ok(Ok(imported)) => imported,
+                Ok(Err(err)) | Err(err) => {
+                    println!("Failed to read {}: {err}", path.display());
+                    return;
+                }
+            };
+            let mut installed = load_or_exit!(InstalledConfig, _cookie_saver);
+            let mut entries: Vec<_> = imported.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            let mut imported_count = 0;
+            for (slug, mut install_info) in entries {
+                if installed.contains_key(&slug) && !force {
+                    println!(
+                        "{slug} is already installed, skipping it. Use --force to replace it."
+                    );
+                    continue;
+                }
+                if verify {
+                    if !install_info.install_path.is_dir() {
+                        println!(
+                            "{} doesn't exist, skipping {slug}.",
+                            install_info.install_path.display()
+                        );
+                        continue;
+                    }
+                    match utils::verify(
+                        &client,
+                        &slug,
+                        &install_info,
+                        false,
+                        Arc::new(Semaphore::new(*constants::DEFAULT_VERIFY_JOBS)),
+                        &output::progress_bars(),
+                    )
+                    .await
+                    {
+                        Ok(result) if result.failures.is_empty() => {
+                            install_info.last_verified = Some(chrono::Utc::now());
+                            install_info.verified_files = result.snapshot;
+                        }
+                        Ok(result) => {
+                            for failure in &result.failures {
+                                println!("{failure}");
+                            }
+                            println!(
+                                "{slug} has {} bad file(s) in {}, skipping it.",
+                                result.failures.len(),
+                                install_info.install_path.display()
+                            );
+                            continue;
+                        }
+                        Err(err) => {
+                            println!("Failed to verify {slug}, skipping it: {err}");
+                            continue;
+                        }
+                    }
                 }
+                // Updating and completing the game read its build manifests back
+                if let Err(err) =
+                    utils::store_installed_manifests(&client, &slug, &install_info).await
+                {
+                    println!("Failed to fetch the build manifests of {slug}, skipping it: {err}");
+                    continue;
+                }
+                installed.insert(slug, install_info);
+                imported_count += 1;
+            }
+
+            installed
+                .store()
+                .expect("Failed to update installed config");
+            println!("Imported {imported_count} installed game(s)");
+        }
+        #[cfg(not(target_os = "windows"))]
+        Commands::Runners => {
+            let runners = helpers::discover_runners();
+            if runners.is_empty() {
+                println!("No WINE or Proton builds were found");
+            }
+            for runner in runners {
+                print_output(&runner);
             }
         }
+        Commands::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                *constants::PROJECT_NAME,
+                &mut std::io::stdout(),
+            );
+        }
+        Commands::Stats => {
+            print_output(&load_or_exit!(UsageConfig, _cookie_saver));
+        }
+        Commands::SelfUpdate { download } => match utils::self_update(&client, download).await {
+            Ok(info) => println!("{}", info),
+            Err(err) => println!("Failed to check for updates: {}", err),
+        },
     };
+}
 
-    drop(client);
-    let cookie_store = Arc::try_unwrap(cookie_store).expect("Failed to unwrap cookie store");
-    let cookie_store = cookie_store
-        .into_inner()
-        .expect("Failed to unwrap CookieStoreMutex");
-    CookieConfig(cookie_store)
-        .store()
-        .expect("Failed to save cookie config");
+/// Saves the session cookies when dropped, so they're kept when a command returns early too.
+/// Failing to save them is only reported, since the command's work is already done by then.
+struct CookieSaver(Arc<CookieStoreMutex>);
+
+impl Drop for CookieSaver {
+    fn drop(&mut self) {
+        let cookie_store = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        // The store holds a single cookie per name/domain/path, and only the persistent,
+        // unexpired ones are saved, so stale or duplicate cookies never pile up
+        if let Err(err) = CookieConfig(cookie_store.clone()).store() {
+            println!("Failed to save cookie config: {err}");
+        }
+    }
 }
 
 fn save_user_info(
@@ -389,3 +1754,44 @@ fn save_user_info(
         .store()
         .expect("Failed to save library config");
 }
+
+/// Loosely checks that `email` looks like an email address, so obvious typos are caught before
+/// sending a login request.
+fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((user, domain)) => {
+            !user.is_empty()
+                && !email.contains(char::is_whitespace)
+                && domain
+                    .split_once('.')
+                    .is_some_and(|(name, tld)| !name.is_empty() && !tld.is_empty())
+        }
+        None => false,
+    }
+}
+
+/// Resolves which installed games a command should act on. An explicit slug always wins,
+/// otherwise every installed game with `tag` is selected (or all of them if there's no tag).
+fn select_slugs(
+    installed: &InstalledConfig,
+    slug: Option<String>,
+    tag: Option<&String>,
+) -> Vec<String> {
+    if let Some(slug) = slug {
+        return vec![slug];
+    }
+
+    let mut slugs: Vec<String> = installed
+        .iter()
+        .filter(|(_, info)| match tag {
+            Some(tag) => info.tags.contains(tag),
+            None => true,
+        })
+        .map(|(slug, _)| slug.to_owned())
+        .collect();
+    slugs.sort();
+    if slugs.is_empty() {
+        println!("No installed games matched.");
+    }
+    slugs
+}
@@ -0,0 +1,157 @@
+use std::{
+    fmt::Display,
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use serde::Serialize;
+use serde_json::json;
+use tokio::{io::AsyncWriteExt, task::JoinHandle};
+
+use crate::cli::Cli;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+static JSON: AtomicBool = AtomicBool::new(false);
+static HEARTBEAT: OnceLock<(PathBuf, Duration)> = OnceLock::new();
+
+/// Applies the global output flags.
+pub(crate) fn init(cli: &Cli) {
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    ASSUME_YES.store(cli.yes, Ordering::Relaxed);
+    JSON.store(cli.json, Ordering::Relaxed);
+    if let Some(heartbeat) = &cli.heartbeat {
+        let _ = HEARTBEAT.set((
+            heartbeat.to_owned(),
+            Duration::from_secs(cli.heartbeat_interval),
+        ));
+    }
+
+    let level = match cli.verbose {
+        0 => "warn",
+        1 => "warn,freecarnival=debug",
+        _ => "debug,freecarnival=trace",
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level))
+        .format_timestamp(None)
+        .init();
+
+    let no_color = cli.no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    console::set_colors_enabled(!no_color && std::io::stdout().is_terminal());
+    console::set_colors_enabled_stderr(!no_color && std::io::stderr().is_terminal());
+}
+
+/// Status messages are hidden with `--quiet`, and with `--json` so they don't get mixed up with
+/// the JSON output.
+pub(crate) fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed) || is_json()
+}
+
+pub(crate) fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Group of progress bars drawn together, hidden like status messages.
+pub(crate) fn progress_bars() -> MultiProgress {
+    if is_quiet() {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
+}
+
+/// Prints a command's output to stdout, as a single line of JSON with `--json` or using its
+/// `Display` impl otherwise.
+pub(crate) fn print_output<T: Serialize + Display>(value: &T) {
+    if is_json() {
+        match serde_json::to_string(value) {
+            Ok(json) => println!("{json}"),
+            Err(err) => println!("Failed to serialize output: {err}"),
+        }
+    } else {
+        println!("{value}");
+    }
+}
+
+/// Asks the user a yes/no question, defaulting to no. Always answers yes when `--yes` is set,
+/// and no when stdin isn't a terminal, since nobody could answer it.
+pub(crate) fn confirm(question: &str) -> bool {
+    if ASSUME_YES.load(Ordering::Relaxed) {
+        return true;
+    }
+    if !std::io::stdin().is_terminal() {
+        eprintln!("{question} Answering no since stdin isn't a terminal, pass --yes to confirm.");
+        return false;
+    }
+
+    eprint!("{question} [y/N] ");
+    if std::io::stderr().flush().is_err() {
+        return false;
+    }
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+/// Starts appending a heartbeat line to the `--heartbeat` file every `--heartbeat-interval`
+/// seconds, until the returned task is aborted. `progress` returns how much of the work is done
+/// and its total, in `unit`s. Returns `None` when no heartbeat was asked for.
+pub(crate) fn heartbeat<F>(
+    phase: &'static str,
+    slug: &str,
+    unit: &'static str,
+    progress: F,
+) -> Option<JoinHandle<()>>
+where
+    F: Fn() -> (u64, u64) + Send + 'static,
+{
+    let (path, period) = HEARTBEAT.get()?.to_owned();
+    let slug = slug.to_owned();
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let (done, total) = progress();
+            let line = json!({
+                "time": chrono::Utc::now().to_rfc3339(),
+                "pid": std::process::id(),
+                "phase": phase,
+                "slug": slug,
+                "done": done,
+                "total": total,
+                "unit": unit,
+            });
+            // The file is reopened every time so it can be rotated or truncated by the
+            // supervisor. A heartbeat that can't be written is skipped, like a missed one.
+            let file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await;
+            if let Ok(mut file) = file {
+                let _ = file.write_all(format!("{line}\n").as_bytes()).await;
+            }
+        }
+    }))
+}
+
+/// Prints a progress/status message to stdout, unless `--quiet` was passed.
+///
+/// Errors and the output a command was asked to produce should keep using `println!`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use status;
@@ -1,8 +1,14 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::SystemTime,
+};
 
+use chrono::{DateTime, NaiveDateTime, Utc};
+use human_bytes::human_bytes;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct InstallInfo {
     /// Directory where game was installed to
     pub(crate) install_path: PathBuf,
@@ -11,6 +17,121 @@ pub(crate) struct InstallInfo {
     /// OS the build is for
     #[serde(default)]
     pub(crate) os: api::BuildOs,
+    /// User defined tags used to group installs for bulk operations
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tags: Vec<String>,
+    /// Launch settings remembered for this game
+    #[serde(default)]
+    pub(crate) launch_options: LaunchOptions,
+    /// winetricks verbs that were already installed in this game's WINE prefix
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) winetricks_verbs: Vec<String>,
+    /// When the game last passed verification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_verified: Option<DateTime<Utc>>,
+    /// Size and modification time of every file when the game last passed verification, keyed
+    /// by file name. `verify --changed` only hashes files that don't match their snapshot.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) verified_files: HashMap<String, FileSnapshot>,
+    /// Files left out by `install --max-size`, to be installed later with `complete`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) deferred_files: Vec<String>,
+    /// Latest version announced by `list-updates --new-only`, so it isn't announced again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) notified_version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FileSnapshot {
+    pub(crate) size: u64,
+    pub(crate) modified: SystemTime,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct LaunchOptions {
+    /// Arguments to run gamescope with. When set, the game is launched through gamescope.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) gamescope: Option<String>,
+    /// GPU to run the game on, see `launch --gpu`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) gpu: Option<String>,
+    /// WINE bin used when `launch` isn't given --wine or --runner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) wine: Option<PathBuf>,
+    /// WINE prefix used when `launch` isn't given --wine-prefix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) wine_prefix: Option<PathBuf>,
+    /// Wrapper used when `launch` isn't given --wrapper
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) wrapper: Option<PathBuf>,
+    /// Arguments passed to the game when `launch` isn't given any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) args: Vec<String>,
+    /// Environment variables set for the game
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) env: BTreeMap<String, String>,
+    /// Command run before the game when `launch` isn't given --pre-launch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pre_launch: Option<String>,
+    /// Command run after the game exits when `launch` isn't given --post-exit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) post_exit: Option<String>,
+}
+
+impl std::fmt::Display for LaunchOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines = vec![];
+        let mut push = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                lines.push(format!("{name}: {value}"));
+            }
+        };
+        push("Wine", self.wine.as_ref().map(|p| p.display().to_string()));
+        push(
+            "Wine Prefix",
+            self.wine_prefix.as_ref().map(|p| p.display().to_string()),
+        );
+        push(
+            "Wrapper",
+            self.wrapper.as_ref().map(|p| p.display().to_string()),
+        );
+        push("Gamescope", self.gamescope.clone());
+        push("GPU", self.gpu.clone());
+        push(
+            "Arguments",
+            (!self.args.is_empty())
+                .then(|| shlex::try_join(self.args.iter().map(|a| a.as_str())).unwrap_or_default()),
+        );
+        push("Pre-launch Hook", self.pre_launch.clone());
+        push("Post-exit Hook", self.post_exit.clone());
+        for (name, value) in &self.env {
+            lines.push(format!("Env: {name}={value}"));
+        }
+        if lines.is_empty() {
+            return write!(f, "No saved launch options");
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// The build of a manifest written by `install --manifest-only`, saved next to it so
+/// `install --manifest` can tell which build it describes
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SavedManifest {
+    pub(crate) slug: String,
+    pub(crate) version: String,
+    pub(crate) os: api::BuildOs,
+}
+
+/// The build saved next to the files downloaded by `install --download-only`, so they can be
+/// installed with `install --from-local`
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DownloadedBuild {
+    pub(crate) slug: String,
+    pub(crate) version: String,
+    pub(crate) os: api::BuildOs,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) deferred_files: Vec<String>,
 }
 
 impl InstallInfo {
@@ -19,7 +140,338 @@ impl InstallInfo {
             install_path,
             version,
             os,
+            tags: vec![],
+            launch_options: LaunchOptions::default(),
+            winetricks_verbs: vec![],
+            last_verified: None,
+            verified_files: HashMap::new(),
+            deferred_files: vec![],
+            notified_version: None,
+        }
+    }
+}
+
+/// Outcome of verifying an installed game
+#[derive(Debug)]
+pub(crate) struct VerifyResult {
+    /// Size and modification time of every file that was found with the right size
+    pub(crate) snapshot: HashMap<String, FileSnapshot>,
+    pub(crate) failures: Vec<VerifyFailure>,
+}
+
+/// A file that failed verification, as written by `verify --report`
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct VerifyFailure {
+    pub(crate) slug: String,
+    pub(crate) file_name: String,
+    pub(crate) reason: VerifyFailureReason,
+    /// Size in the build manifest, for files with the wrong size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) expected_size: Option<u64>,
+    /// Size on disk, for files with the wrong size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) found_size: Option<u64>,
+    /// Why the file couldn't be hashed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+impl std::fmt::Display for VerifyFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.reason, &self.error) {
+            (_, Some(error)) => write!(f, "Failed to verify {}: {}", self.file_name, error),
+            (VerifyFailureReason::Missing, None) => write!(f, "{} is missing", self.file_name),
+            (VerifyFailureReason::Size, None) => write!(
+                f,
+                "{} has the wrong size (expected {} bytes, found {})",
+                self.file_name,
+                self.expected_size.unwrap_or_default(),
+                self.found_size.unwrap_or_default()
+            ),
+            (VerifyFailureReason::Hash, None) => {
+                write!(f, "{} doesn't match its hash", self.file_name)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum VerifyFailureReason {
+    Missing,
+    Size,
+    Hash,
+}
+
+/// Network statistics collected while downloading a build.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct DownloadStats {
+    pub(crate) total_bytes: u64,
+    pub(crate) elapsed_secs: f64,
+    pub(crate) average_bytes_per_sec: f64,
+    pub(crate) peak_bytes_per_sec: f64,
+    pub(crate) retries: u64,
+    #[serde(skip)]
+    pub(crate) timings: PhaseTimings,
+}
+
+/// Time spent in each phase of an install, printed with `--profile`. The download phases are
+/// summed across workers running in parallel, so they can add up to more than the elapsed time.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct PhaseTimings {
+    pub(crate) manifest_secs: f64,
+    pub(crate) network_secs: f64,
+    pub(crate) verify_secs: f64,
+    pub(crate) write_secs: f64,
+    pub(crate) move_secs: f64,
+    pub(crate) elapsed_secs: f64,
+}
+
+impl std::fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Manifests: {:.1}s", self.manifest_secs)?;
+        writeln!(f, "Network: {:.1}s", self.network_secs)?;
+        writeln!(f, "Chunk Verification: {:.1}s", self.verify_secs)?;
+        writeln!(f, "Disk Write: {:.1}s", self.write_secs)?;
+        writeln!(f, "Moving Files: {:.1}s", self.move_secs)?;
+        write!(
+            f,
+            "Download Elapsed: {:.1}s (phases are summed across parallel workers)",
+            self.elapsed_secs
+        )
+    }
+}
+
+impl std::fmt::Display for DownloadStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Downloaded: {}", human_bytes(self.total_bytes as f64))?;
+        writeln!(f, "Elapsed: {:.1}s", self.elapsed_secs)?;
+        writeln!(
+            f,
+            "Average Throughput: {}/s",
+            human_bytes(self.average_bytes_per_sec)
+        )?;
+        writeln!(
+            f,
+            "Peak Throughput: {}/s",
+            human_bytes(self.peak_bytes_per_sec)
+        )?;
+        write!(f, "Retries: {}", self.retries)
+    }
+}
+
+/// HTTP version used to talk to indieGala's servers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum HttpVersion {
+    /// Negotiate the version with the server
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "1.1")]
+    Http1,
+    #[serde(rename = "2")]
+    Http2,
+}
+
+/// Files touched by an update, from its delta manifest
+#[derive(Debug, Serialize)]
+pub(crate) struct UpdateSummary {
+    pub(crate) added: usize,
+    pub(crate) changed: usize,
+    pub(crate) removed: usize,
+    pub(crate) downloaded_bytes: u64,
+}
+
+impl std::fmt::Display for UpdateSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files added, {} changed, {} removed ({} downloaded)",
+            self.added,
+            self.changed,
+            self.removed,
+            human_bytes(self.downloaded_bytes as f64)
+        )
+    }
+}
+
+/// What a command that downloads files did, printed once by `main` when it's done
+#[derive(Debug, Serialize)]
+pub(crate) struct DownloadReport {
+    pub(crate) message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stats: Option<DownloadStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) update: Option<UpdateSummary>,
+    /// Only set with `--profile`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) timings: Option<PhaseTimings>,
+}
+
+impl From<String> for DownloadReport {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            stats: None,
+            update: None,
+            timings: None,
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(stats) = &self.stats {
+            writeln!(f, "{stats}")?;
+        }
+        if let Some(update) = &self.update {
+            writeln!(f, "{update}")?;
+        }
+        if let Some(timings) = &self.timings {
+            writeln!(f, "{timings}")?;
         }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// An update listed by the `list-updates` command
+#[derive(Debug, Serialize)]
+pub(crate) struct AvailableUpdate {
+    pub(crate) slug: String,
+    pub(crate) current_version: String,
+    pub(crate) latest_version: String,
+}
+
+impl std::fmt::Display for AvailableUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} has an update -> {}", self.slug, self.latest_version)
+    }
+}
+
+/// Outcome of verifying one game with the `verify` command
+#[derive(Debug, Serialize)]
+pub(crate) struct VerifyOutcome {
+    pub(crate) slug: String,
+    pub(crate) ok: bool,
+    pub(crate) bad_files: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) failures: Vec<VerifyFailure>,
+}
+
+impl std::fmt::Display for VerifyOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for failure in &self.failures {
+            writeln!(f, "{failure}")?;
+        }
+        match &self.error {
+            Some(error) => write!(f, "Failed to verify {}: {}", self.slug, error),
+            None if self.ok => write!(f, "{} passed verification.", self.slug),
+            None => write!(
+                f,
+                "{} is corrupted ({} bad file(s)). Run `repair {}` to fix it.",
+                self.slug, self.bad_files, self.slug
+            ),
+        }
+    }
+}
+
+/// A game listed by the `list-installed` command
+#[derive(Debug, Serialize)]
+pub(crate) struct InstalledEntry {
+    pub(crate) slug: String,
+    pub(crate) version: String,
+    pub(crate) os: api::BuildOs,
+    pub(crate) install_path: PathBuf,
+    /// `None` when the install path doesn't exist anymore
+    pub(crate) size_in_bytes: Option<u64>,
+}
+
+impl std::fmt::Display for InstalledEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} [{}] {} ",
+            self.slug,
+            self.version,
+            self.os,
+            self.install_path.display()
+        )?;
+        match self.size_in_bytes {
+            Some(size) => write!(f, "{}", human_bytes(size as f64)),
+            None => write!(f, "(missing)"),
+        }
+    }
+}
+
+/// A build listed by the `versions` command
+#[derive(Debug, Serialize)]
+pub(crate) struct VersionEntry {
+    pub(crate) version: String,
+    pub(crate) os: api::BuildOs,
+    pub(crate) date: NaiveDateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) size_in_bytes: Option<u64>,
+    pub(crate) installed: bool,
+}
+
+impl std::fmt::Display for VersionEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} [{}] {}",
+            if self.installed { "*" } else { " " },
+            self.version,
+            self.os,
+            self.date
+        )?;
+        if let Some(size) = self.size_in_bytes {
+            write!(f, " {}", human_bytes(size as f64))?;
+        }
+        if self.installed {
+            write!(f, " (installed)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything the `info` command knows about a game
+#[derive(Debug, Serialize)]
+pub(crate) struct GameInfo<'a> {
+    pub(crate) product: &'a api::Product,
+    pub(crate) versions: Vec<VersionEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) installed: Option<InstalledGame<'a>>,
+}
+
+/// An entry of `info --json` when it's given more than one slug
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub(crate) enum InfoEntry<'a> {
+    Found(GameInfo<'a>),
+    Missing { slug: String, error: String },
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct InstalledGame<'a> {
+    pub(crate) version: &'a str,
+    pub(crate) os: &'a api::BuildOs,
+    pub(crate) install_path: &'a PathBuf,
+}
+
+impl std::fmt::Display for GameInfo<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Available Versions:\n{}",
+            self.product
+                .version
+                .iter()
+                .map(|v| format!("\n{}", v))
+                .collect::<Vec<String>>()
+                .join("\n")
+        )
     }
 }
 
@@ -93,14 +545,10 @@ pub(crate) mod api {
     use chrono::NaiveDateTime;
     use serde::{Deserialize, Serialize};
 
-    use crate::config::{LibraryConfig, UserConfig};
-
-    #[derive(Debug, Serialize)]
-    pub(crate) struct LatestBuildNumberPayload {
-        dev_id: String,
-        id_key_name: String,
-        os_selected: String,
-    }
+    use crate::{
+        config::{LibraryConfig, UserConfig},
+        error::FreeCarnivalError,
+    };
 
     #[derive(Debug, Deserialize)]
     pub(crate) struct GameDetailsResponse {
@@ -122,6 +570,18 @@ pub(crate) mod api {
         pub(crate) message: String,
     }
 
+    impl LoginResult {
+        pub(crate) fn is_success(&self) -> bool {
+            self.status == "success"
+        }
+
+        /// Whether the server wants the security code it emailed before logging in, rather
+        /// than rejecting the email or password
+        pub(crate) fn needs_security_code(&self) -> bool {
+            self.status == "code_required"
+        }
+    }
+
     pub(crate) struct SyncResult {
         pub(crate) user_config: UserConfig,
         pub(crate) library_config: LibraryConfig,
@@ -170,15 +630,9 @@ pub(crate) mod api {
 
     impl Product {
         pub(crate) fn get_latest_version(&self, os: Option<&BuildOs>) -> Option<&ProductVersion> {
+            let target_os = os.cloned().unwrap_or_else(BuildOs::native);
             self.version.iter().fold(None, |acc, version| {
-                let valid_os = match os {
-                    Some(build_os) => version.os == *build_os,
-                    #[cfg(target_os = "macos")]
-                    None => version.os == BuildOs::Mac,
-                    #[cfg(not(target_os = "macos"))]
-                    None => version.os == BuildOs::Windows,
-                };
-                if !valid_os {
+                if version.os != target_os {
                     return acc;
                 }
 
@@ -194,6 +648,56 @@ pub(crate) mod api {
                 }
             })
         }
+
+        /// Fills the `{slug}`, `{name}`, `{id}` and `{namespace}` placeholders of `template`
+        /// with this product's fields.
+        pub(crate) fn render(&self, template: &str) -> String {
+            template
+                .replace("{slug}", &self.slugged_name)
+                .replace("{name}", &self.name)
+                .replace("{id}", &self.id.to_string())
+                .replace("{namespace}", &self.namespace)
+        }
+
+        /// The error for when no build matches `os` (the native OS if `None`). When the product
+        /// has no build at all for that OS, the OSes it does have builds for are listed.
+        pub(crate) fn missing_build_error(&self, os: Option<&BuildOs>) -> FreeCarnivalError {
+            let target_os = os.cloned().unwrap_or_else(BuildOs::native);
+            if self.version.iter().any(|version| version.os == target_os) {
+                return FreeCarnivalError::InstallBuild(self.slugged_name.to_owned());
+            }
+
+            let mut available: Vec<String> = vec![];
+            for version in &self.version {
+                let os = version.os.to_string();
+                if !available.contains(&os) {
+                    available.push(os);
+                }
+            }
+            FreeCarnivalError::NoBuildForOs {
+                slug: self.slugged_name.to_owned(),
+                os: target_os,
+                available,
+            }
+        }
+    }
+
+    /// A published release of this program
+    #[derive(Deserialize, Debug)]
+    pub(crate) struct Release {
+        pub(crate) tag_name: String,
+        pub(crate) html_url: String,
+        #[serde(default)]
+        pub(crate) assets: Vec<ReleaseAsset>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub(crate) struct ReleaseAsset {
+        pub(crate) name: String,
+        pub(crate) browser_download_url: String,
+        /// `sha256:<hex>`, for assets uploaded since GitHub started computing it
+        #[serde(default)]
+        pub(crate) digest: Option<String>,
     }
 
     #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -206,8 +710,9 @@ pub(crate) mod api {
         pub(crate) text: String,
     }
 
-    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
     pub(crate) enum BuildOs {
+        #[default]
         #[serde(rename = "win")]
         Windows,
         #[serde(rename = "lin")]
@@ -216,9 +721,13 @@ pub(crate) mod api {
         Mac,
     }
 
-    impl Default for BuildOs {
-        fn default() -> Self {
-            Self::Windows
+    impl BuildOs {
+        /// The OS builds are picked for when none is requested
+        pub(crate) fn native() -> Self {
+            #[cfg(target_os = "macos")]
+            return BuildOs::Mac;
+            #[cfg(not(target_os = "macos"))]
+            return BuildOs::Windows;
         }
     }
 
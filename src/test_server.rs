@@ -0,0 +1,77 @@
+//! A minimal HTTP server the API URLs point to in tests. Each test registers the responses of
+//! its own paths, so tests running in parallel don't see each other's requests.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::config;
+
+type Handler = Arc<dyn Fn() -> (u16, Vec<u8>) + Send + Sync>;
+
+static ROUTES: OnceLock<Mutex<HashMap<String, Handler>>> = OnceLock::new();
+static URL: OnceLock<String> = OnceLock::new();
+
+/// The server's URL, starting it on first use. Configs are stored in a temporary directory so
+/// tests never touch the user's own.
+pub(crate) fn url() -> &'static str {
+    URL.get_or_init(|| {
+        let config_dir =
+            std::env::temp_dir().join(format!("freecarnival-test-{}", std::process::id()));
+        config::set_config_dir(config_dir);
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind test server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                std::thread::spawn(move || respond(stream));
+            }
+        });
+        url
+    })
+}
+
+/// Answers requests to `path` with the status and body `handler` returns. Requests to paths
+/// without a handler get a 404.
+pub(crate) fn route(path: &str, handler: impl Fn() -> (u16, Vec<u8>) + Send + Sync + 'static) {
+    ROUTES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .insert(path.to_owned(), Arc::new(handler));
+}
+
+fn respond(mut stream: TcpStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    // The headers aren't needed, they're read so the client doesn't see the connection reset
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|len| len > 2) {
+        header.clear();
+    }
+
+    let path = request_line.split(' ').nth(1).unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    let handler = ROUTES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .get(path)
+        .cloned();
+    let (status, body) = match handler {
+        Some(handler) => handler(),
+        None => (404, vec![]),
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {status} Test\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    let _ = stream.write_all(&body);
+}
@@ -1,66 +1,145 @@
-use std::{collections::HashMap, path::PathBuf, process::ExitStatus, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
 
 use human_bytes::human_bytes;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use os_path::OsPath;
 use regex::Regex;
+use sha2::{Digest, Sha256};
 use shlex::split;
-use tokio::task::JoinHandle;
+use tokio::{sync::Semaphore, task::JoinHandle, time::timeout};
 
+#[cfg(target_os = "linux")]
+use crate::helpers::find_elf_recursive;
+#[cfg(target_os = "linux")]
+use crate::helpers::gpu_env;
+#[cfg(not(target_os = "windows"))]
+use crate::helpers::is_32bit_exe;
 #[cfg(target_os = "macos")]
-use crate::helpers::mac::{find_app_recursive, find_info_plist, MacAppExecutables};
+use crate::helpers::{
+    find_mach_o_recursive,
+    mac::{find_app_recursive, find_info_plist, MacAppExecutables},
+};
 use crate::{
     api,
-    cli::InstallOpts,
-    config::{GalaConfig, InstalledConfig, LibraryConfig},
+    cli::{HookOpts, InstallOpts},
+    config::{GalaConfig, InstalledConfig, LibraryConfig, SettingsConfig},
+    constants::{
+        DEFAULT_VERIFY_JOBS, MAX_CHUNK_SIZE, MAX_REPAIR_ATTEMPTS, PROJECT_NAME, PROJECT_VERSION,
+    },
+    error::FreeCarnivalError,
     helpers::{
-        build_from_manifest, find_exe_recursive, read_build_manifest,
-        read_or_generate_delta_chunks_manifest, read_or_generate_delta_manifest,
-        store_build_manifest, verify_file_hash,
+        available_space, build_from_manifest, chunk_sha, compare_versions, copy_dir, count_files,
+        dir_size, download_chunk_refreshing_session, ensure_writable, filter_manifests,
+        find_bad_chunks, find_case_collisions, find_exe_recursive, find_in_path, find_program,
+        install_hint, is_case_insensitive, long_path, manifest_file, move_dir, read_build_manifest,
+        read_local_manifest, read_or_generate_delta_chunks_manifest,
+        read_or_generate_delta_manifest, remove_unprotected, same_filesystem, store_build_manifest,
+        verify_chunk, verify_file_hash, write_chunk_at, ProtectedPaths, RateLimiter,
     },
+    output::{heartbeat, is_quiet, status},
     shared::models::{
-        api::{BuildOs, Product, ProductVersion},
-        BuildManifestRecord, ChangeTag, InstallInfo,
+        api::{BuildOs, Product, ProductVersion, Release, ReleaseAsset},
+        BuildManifestChunksRecord, BuildManifestRecord, ChangeTag, DownloadReport, DownloadedBuild,
+        FileSnapshot, InstallInfo, SavedManifest, UpdateSummary, VerifyFailure,
+        VerifyFailureReason, VerifyResult,
     },
 };
 
 // TODO: Refactor info printing and chunk downloading to separate functions
-pub(crate) async fn install<'a>(
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn install(
     client: reqwest::Client,
     slug: &String,
     install_path: &PathBuf,
+    staging_path: &PathBuf,
     install_opts: InstallOpts,
+    download_budget: Arc<Semaphore>,
     version: Option<&ProductVersion>,
     os: Option<BuildOs>,
-) -> Result<Result<(String, Option<InstallInfo>), &'a str>, reqwest::Error> {
-    let library = LibraryConfig::load().expect("Failed to load library");
+    max_size: Option<u64>,
+    force: bool,
+    resume: bool,
+    space_check: bool,
+    local_manifest: Option<&Path>,
+    download_only: bool,
+) -> Result<(DownloadReport, Option<InstallInfo>), FreeCarnivalError> {
+    let library = LibraryConfig::load()?;
     let product = match library.collection.iter().find(|p| p.slugged_name == *slug) {
         Some(product) => product,
         None => {
-            return Ok(Err("Could not find game in library"));
+            return Err(FreeCarnivalError::GameNotFound(slug.to_owned()));
         }
     };
 
-    let build_version = match version {
-        Some(selected) => selected,
-        None => match product.get_latest_version(os.as_ref()) {
+    let manifest_started_at = Instant::now();
+    let local_manifest = match local_manifest {
+        Some(path) => {
+            status!("Reading build manifest from {}...", path.display());
+            Some((path, read_local_manifest(path).await?))
+        }
+        None => None,
+    };
+    let build_version = match (version, &local_manifest) {
+        (Some(selected), _) => selected,
+        // Without --version, a saved manifest is installed as the build it was written for
+        (None, Some((_, (saved, _, _)))) => match product
+            .version
+            .iter()
+            .find(|v| v.version == saved.version && v.os == saved.os)
+        {
+            Some(saved_version) => saved_version,
+            None => {
+                return Err(FreeCarnivalError::InstallBuild(format!(
+                    "{slug} {} for {}",
+                    saved.version, saved.os
+                )));
+            }
+        },
+        (None, None) => match product.get_latest_version(os.as_ref()) {
             Some(latest) => latest,
             None => {
-                return Ok(Err("Failed to fetch latest build number. Cannot install."));
+                return Err(product.missing_build_error(os.as_ref()));
             }
         },
     };
-    println!("Found game. Installing build version {}...", build_version);
+    status!("Found game. Installing build version {}...", build_version);
 
-    println!("Fetching build manifest...");
-    let build_manifest = api::product::get_build_manifest(&client, product, build_version).await?;
+    let (build_manifest, local_manifest_chunks) = match local_manifest {
+        Some((path, (saved, manifest, chunks))) => {
+            if saved.slug != *slug
+                || saved.version != build_version.version
+                || saved.os != build_version.os
+            {
+                return Err(FreeCarnivalError::ManifestMismatch(
+                    path.to_path_buf(),
+                    build_version.to_string(),
+                ));
+            }
+            (manifest, Some(chunks))
+        }
+        None => {
+            status!("Fetching build manifest...");
+            let manifest = api::product::get_build_manifest(&client, product, build_version)
+                .await?
+                .to_vec();
+            (manifest, None)
+        }
+    };
     store_build_manifest(
         &build_manifest,
         &build_version.version,
         &product.slugged_name,
         "manifest",
     )
-    .await
-    .expect("Failed to save build manifest");
+    .await?;
+    let mut manifest_elapsed = manifest_started_at.elapsed();
 
     if install_opts.info {
         let mut build_manifest_rdr = csv::Reader::from_reader(&build_manifest[..]);
@@ -79,57 +158,644 @@ pub(crate) async fn install<'a>(
         let mut buf = String::new();
         buf.push_str(&format!("Download Size: {}", human_bytes(download_size)));
         buf.push_str(&format!("\nDisk Size: {}", human_bytes(download_size)));
-        return Ok(Ok((buf, None)));
+        return Ok((buf.into(), None));
     }
 
-    println!("Fetching build manifest chunks...");
-    let build_manifest_chunks =
-        api::product::get_build_manifest_chunks(&client, product, build_version).await?;
+    if !download_only {
+        ensure_writable(install_path).await?;
+    }
+    ensure_writable(staging_path).await?;
+
+    let (records, _, _) = filter_manifests(&build_manifest[..], &[], |_| true);
+    let collisions = find_case_collisions(&records);
+    // Only the last file of each colliding pair is installed, so they aren't written on top of
+    // each other
+    let overwritten: HashSet<String> =
+        if !collisions.is_empty() && is_case_insensitive(staging_path).await? {
+            let pairs = collisions
+                .iter()
+                .map(|(first, second)| format!("  {first} <-> {second}"))
+                .collect::<Vec<String>>()
+                .join("\n");
+            if !force {
+                return Err(FreeCarnivalError::CaseCollision(pairs));
+            }
+            println!("Keeping the last file of each pair that only differs in case:\n{pairs}");
+            collisions.into_iter().map(|(first, _)| first).collect()
+        } else {
+            HashSet::new()
+        };
+
+    let manifest_started_at = Instant::now();
+    let build_manifest_chunks = match local_manifest_chunks {
+        Some(chunks) => chunks,
+        None => {
+            status!("Fetching build manifest chunks...");
+            api::product::get_build_manifest_chunks(&client, product, build_version)
+                .await?
+                .to_vec()
+        }
+    };
     store_build_manifest(
         &build_manifest_chunks,
         &build_version.version,
         &product.slugged_name,
         "manifest_chunks",
     )
-    .await
-    .expect("Failed to save build manifest chunks");
+    .await?;
+    manifest_elapsed += manifest_started_at.elapsed();
+
+    // With --max-size, files are kept in manifest order until one doesn't fit, deferring it and
+    // every file after it
+    let mut deferred_files = vec![];
+    let mut deferred_bytes = 0;
+    let mut total_size = 0;
+    let (_, build_manifest, build_manifest_chunks) =
+        filter_manifests(&build_manifest[..], &build_manifest_chunks[..], |record| {
+            if overwritten.contains(&record.file_name) {
+                return false;
+            }
+            let size = record.size_in_bytes as u64;
+            let fits = max_size
+                .is_none_or(|max_size| deferred_files.is_empty() && total_size + size <= max_size);
+            if record.is_directory() || fits {
+                total_size += size;
+                return true;
+            }
+            deferred_files.push(record.file_name.to_owned());
+            deferred_bytes += size;
+            false
+        });
+
+    if space_check {
+        // Chunks already downloaded by an interrupted install don't need space again
+        let downloaded = if resume {
+            dir_size(staging_path).await.unwrap_or_default()
+        } else {
+            0
+        };
+        let required = total_size.saturating_sub(downloaded);
+        let paths = if download_only {
+            vec![staging_path]
+        } else {
+            vec![staging_path, install_path]
+        };
+        for path in paths {
+            match available_space(path) {
+                Ok(available) if available < required => {
+                    return Err(FreeCarnivalError::InsufficientSpace {
+                        path: path.to_owned(),
+                        required,
+                        available,
+                    });
+                }
+                Ok(_) => {}
+                Err(err) => println!("Couldn't check free space of {}: {}", path.display(), err),
+            }
+        }
+    }
 
     let product_arc = Arc::new(product.clone());
     let os_arc = Arc::new(build_version.os.to_owned());
 
-    println!("Installing game from manifest...");
-    let result = build_from_manifest(
+    status!("Installing game from manifest...");
+    let profile = install_opts.profile;
+    let mut stats = build_from_manifest(
         client,
         product_arc,
         os_arc,
         &build_manifest[..],
         &build_manifest_chunks[..],
-        install_path.into(),
+        staging_path.into(),
         install_opts,
+        download_budget,
+        resume,
     )
-    .await
-    .expect("Failed to build from manifest");
-
-    match result {
-        true => {
-            let install_info = InstallInfo::new(
-                install_path.to_owned(),
-                build_version.version.to_owned(),
-                build_version.os.to_owned(),
-            );
-            Ok(Ok((
-                format!("Successfully installed {} ({})", slug, build_version),
-                Some(install_info),
-            )))
+    .await?;
+    let mut timings = std::mem::take(&mut stats.timings);
+    timings.manifest_secs = manifest_elapsed.as_secs_f64();
+
+    if download_only {
+        // The whole manifests are saved, so deferred files can still be completed once installed
+        let downloaded = DownloadedBuild {
+            slug: slug.to_owned(),
+            version: build_version.version.to_owned(),
+            os: build_version.os.to_owned(),
+            deferred_files,
+        };
+        let manifest = read_build_manifest(&build_version.version, slug, "manifest").await?;
+        let manifest_chunks =
+            read_build_manifest(&build_version.version, slug, "manifest_chunks").await?;
+        tokio::fs::write(download_file(staging_path, "manifest.csv"), manifest).await?;
+        tokio::fs::write(
+            download_file(staging_path, "manifest-chunks.csv"),
+            manifest_chunks,
+        )
+        .await?;
+        let json = serde_json::to_vec_pretty(&downloaded).map_err(std::io::Error::other)?;
+        tokio::fs::write(download_file(staging_path, "download.json"), json).await?;
+        let report = DownloadReport {
+            message: format!(
+                "Downloaded {slug} ({build_version}) to {}. Run `install {slug} --from-local {}` to install it.",
+                staging_path.display(),
+                staging_path.display()
+            ),
+            stats: Some(stats),
+            update: None,
+            timings: profile.then_some(timings),
+        };
+        return Ok((report, None));
+    }
+
+    status!(
+        "Moving game from {} to install path...",
+        staging_path.display()
+    );
+    let move_started_at = Instant::now();
+    move_dir(&long_path(staging_path), &long_path(install_path)).await?;
+    timings.move_secs = move_started_at.elapsed().as_secs_f64();
+
+    let mut install_info = InstallInfo::new(
+        install_path.to_owned(),
+        build_version.version.to_owned(),
+        build_version.os.to_owned(),
+    );
+    let mut message = format!("Successfully installed {} ({})", slug, build_version);
+    if !deferred_files.is_empty() {
+        message.push_str(&format!(
+            "\nDeferred {} file(s) ({}). Run `complete {slug}` to install them.",
+            deferred_files.len(),
+            human_bytes(deferred_bytes as f64)
+        ));
+        install_info.deferred_files = deferred_files;
+    }
+    let report = DownloadReport {
+        message,
+        stats: Some(stats),
+        update: None,
+        timings: profile.then_some(timings),
+    };
+    Ok((report, Some(install_info)))
+}
+
+/// Path of a file saved by `install --download-only` next to the downloaded build.
+fn download_file(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!(".{}-{name}", *PROJECT_NAME))
+}
+
+/// Installs a build downloaded with `install --download-only` from `local_dir`, checking its
+/// files against the manifest saved with them before copying them to `install_path`.
+/// `local_dir` is left as it was, so the same download can be installed again.
+pub(crate) async fn install_from_local(
+    slug: &String,
+    local_dir: &Path,
+    install_path: &Path,
+) -> Result<(DownloadReport, Option<InstallInfo>), FreeCarnivalError> {
+    let info_path = download_file(local_dir, "download.json");
+    let downloaded: DownloadedBuild = match tokio::fs::read(&info_path).await {
+        Ok(json) => serde_json::from_slice(&json).map_err(std::io::Error::other)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(FreeCarnivalError::NoLocalDownload(local_dir.to_owned()));
         }
-        false => Ok(Err(
-            "Some chunks failed verification. Failed to install game.",
-        )),
+        Err(err) => return Err(err.into()),
+    };
+    if downloaded.slug != *slug {
+        return Err(FreeCarnivalError::LocalDownloadMismatch {
+            path: local_dir.to_owned(),
+            slug: slug.to_owned(),
+            found: downloaded.slug,
+        });
+    }
+    let build_manifest = tokio::fs::read(download_file(local_dir, "manifest.csv")).await?;
+    let build_manifest_chunks =
+        tokio::fs::read(download_file(local_dir, "manifest-chunks.csv")).await?;
+
+    ensure_writable(install_path).await?;
+    let (records, _, _) = filter_manifests(&build_manifest[..], &[], |r| {
+        !r.is_directory() && !downloaded.deferred_files.contains(&r.file_name)
+    });
+    status!("Verifying {} downloaded file(s)...", records.len());
+    let corrupted = find_corrupted(local_dir, &records).await?;
+    if !corrupted.is_empty() {
+        return Err(FreeCarnivalError::Verify(format!(
+            "These downloaded files of {slug} are missing or corrupted:\n{}",
+            corrupted.join("\n")
+        )));
+    }
+
+    store_build_manifest(&build_manifest, &downloaded.version, slug, "manifest").await?;
+    store_build_manifest(
+        &build_manifest_chunks,
+        &downloaded.version,
+        slug,
+        "manifest_chunks",
+    )
+    .await?;
+
+    status!(
+        "Copying game from {} to install path...",
+        local_dir.display()
+    );
+    copy_dir(&long_path(local_dir), &long_path(install_path)).await?;
+    // The saved build is only needed in the download directory
+    for name in ["download.json", "manifest.csv", "manifest-chunks.csv"] {
+        tokio::fs::remove_file(download_file(install_path, name)).await?;
+    }
+
+    let message = format!(
+        "Successfully installed {slug} ({} for {}) from {}",
+        downloaded.version,
+        downloaded.os,
+        local_dir.display()
+    );
+    let mut install_info =
+        InstallInfo::new(install_path.to_owned(), downloaded.version, downloaded.os);
+    install_info.deferred_files = downloaded.deferred_files;
+    Ok((message.into(), Some(install_info)))
+}
+
+/// Installs the files of an installed game that were deferred by `install --max-size`.
+pub(crate) async fn complete(
+    client: reqwest::Client,
+    library: &LibraryConfig,
+    slug: &String,
+    install_opts: InstallOpts,
+    download_budget: Arc<Semaphore>,
+    install_info: &InstallInfo,
+) -> Result<(DownloadReport, Option<InstallInfo>), FreeCarnivalError> {
+    if install_info.deferred_files.is_empty() {
+        return Ok((format!("{slug} is already fully installed.").into(), None));
+    }
+    let product = match library.collection.iter().find(|p| &p.slugged_name == slug) {
+        Some(p) => p,
+        None => {
+            return Err(FreeCarnivalError::GameNotFound(slug.to_owned()));
+        }
+    };
+
+    let build_manifest = read_build_manifest(&install_info.version, slug, "manifest").await?;
+    let build_manifest_chunks =
+        read_build_manifest(&install_info.version, slug, "manifest_chunks").await?;
+    let (records, build_manifest, build_manifest_chunks) =
+        filter_manifests(&build_manifest[..], &build_manifest_chunks[..], |record| {
+            install_info.deferred_files.contains(&record.file_name)
+        });
+
+    let download_size: usize = records.iter().map(|r| r.size_in_bytes).sum();
+    if install_opts.info {
+        return Ok((
+            format!(
+                "Files: {}\nDownload Size: {}",
+                records.len(),
+                human_bytes(download_size as f64)
+            )
+            .into(),
+            None,
+        ));
+    }
+
+    ensure_writable(&install_info.install_path).await?;
+    status!("Installing {} deferred file(s)...", records.len());
+    let profile = install_opts.profile;
+    let mut stats = build_from_manifest(
+        client,
+        Arc::new(product.clone()),
+        Arc::new(install_info.os.to_owned()),
+        &build_manifest[..],
+        &build_manifest_chunks[..],
+        OsPath::from(&install_info.install_path),
+        install_opts,
+        download_budget,
+        false,
+    )
+    .await?;
+    let timings = std::mem::take(&mut stats.timings);
+
+    let install_info = InstallInfo {
+        deferred_files: vec![],
+        ..install_info.clone()
+    };
+    let report = DownloadReport {
+        message: format!("{slug} is now fully installed."),
+        stats: Some(stats),
+        update: None,
+        timings: profile.then_some(timings),
+    };
+    Ok((report, Some(install_info)))
+}
+
+/// Fetches the build manifest of `version` to get its total download size.
+pub(crate) async fn build_size(
+    client: &reqwest::Client,
+    product: &Product,
+    version: &ProductVersion,
+) -> Result<u64, FreeCarnivalError> {
+    let build_manifest = api::product::get_build_manifest(client, product, version).await?;
+    let (records, _, _) = filter_manifests(&build_manifest[..], &[], |_| true);
+
+    Ok(records.iter().map(|r| r.size_in_bytes as u64).sum())
+}
+
+/// Picks the first of `base_paths` with enough free space to install `version`. Without
+/// `check_space`, the first one is picked without checking.
+pub(crate) async fn select_base_path(
+    client: &reqwest::Client,
+    product: &Product,
+    version: &ProductVersion,
+    base_paths: &[PathBuf],
+    check_space: bool,
+) -> Result<PathBuf, FreeCarnivalError> {
+    if !check_space {
+        return base_paths.first().cloned().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "No base paths are set").into()
+        });
+    }
+
+    status!("Fetching build size...");
+    let size = build_size(client, product, version).await?;
+    // The one closest to fitting the build is reported if none of them do
+    let mut most_space: Option<(&PathBuf, u64)> = None;
+    for base_path in base_paths {
+        match available_space(base_path) {
+            Ok(available) if available >= size => {
+                println!(
+                    "Installing to {} ({} free)",
+                    base_path.display(),
+                    human_bytes(available as f64)
+                );
+                return Ok(base_path.to_owned());
+            }
+            Ok(available) => {
+                status!(
+                    "{} only has {} free, skipping",
+                    base_path.display(),
+                    human_bytes(available as f64)
+                );
+                if most_space.is_none_or(|(_, most)| available > most) {
+                    most_space = Some((base_path, available));
+                }
+            }
+            Err(err) => println!(
+                "Couldn't check free space of {}, skipping: {}",
+                base_path.display(),
+                err
+            ),
+        }
+    }
+
+    match most_space {
+        Some((path, available)) => Err(FreeCarnivalError::InsufficientSpace {
+            path: path.to_owned(),
+            required: size,
+            available,
+        }),
+        None => Err(std::io::Error::other("Couldn't check the free space of any base path").into()),
     }
 }
 
-pub(crate) async fn uninstall(install_path: &PathBuf) -> tokio::io::Result<()> {
-    tokio::fs::remove_dir_all(install_path).await
+/// Writes the build manifest of `version` to `out` without downloading any chunks. The chunks
+/// manifest and the build are saved next to it, so `install --manifest` doesn't need to fetch
+/// anything but the chunks.
+pub(crate) async fn dump_manifest(
+    client: &reqwest::Client,
+    product: &Product,
+    version: &ProductVersion,
+    out: &PathBuf,
+) -> Result<String, FreeCarnivalError> {
+    status!("Fetching build manifest...");
+    let build_manifest = api::product::get_build_manifest(client, product, version).await?;
+    status!("Fetching build manifest chunks...");
+    let build_manifest_chunks =
+        api::product::get_build_manifest_chunks(client, product, version).await?;
+    let (records, _, _) = filter_manifests(&build_manifest[..], &[], |_| true);
+
+    let is_json = out
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if is_json {
+        let records: Vec<serde_json::Value> = records
+            .iter()
+            .map(|record| {
+                serde_json::json!({
+                    "file_name": record.file_name,
+                    "size_in_bytes": record.size_in_bytes,
+                    "chunks": record.chunks,
+                    "sha": record.sha,
+                    "flags": record.flags,
+                    "tag": record.tag,
+                })
+            })
+            .collect();
+        let json = serde_json::to_vec_pretty(&records).expect("Failed to serialize manifest");
+        tokio::fs::write(out, json).await?;
+    } else {
+        tokio::fs::write(out, &build_manifest).await?;
+    }
+    tokio::fs::write(manifest_file(out, "chunks.csv"), &build_manifest_chunks).await?;
+    let saved = SavedManifest {
+        slug: product.slugged_name.to_owned(),
+        version: version.version.to_owned(),
+        os: version.os.to_owned(),
+    };
+    let json = serde_json::to_vec_pretty(&saved).map_err(std::io::Error::other)?;
+    tokio::fs::write(manifest_file(out, "build.json"), json).await?;
+
+    Ok(format!(
+        "Wrote manifest for {} {} ({} entries) to {}",
+        product.name,
+        version,
+        records.len(),
+        out.display()
+    ))
+}
+
+/// Downloads the manifest entry at `path` (and everything under it, if it's a directory) into
+/// `out`, without registering anything as installed.
+pub(crate) async fn fetch(
+    client: reqwest::Client,
+    product: &Product,
+    version: &ProductVersion,
+    path: &str,
+    out: &PathBuf,
+    install_opts: InstallOpts,
+    download_budget: Arc<Semaphore>,
+) -> Result<DownloadReport, FreeCarnivalError> {
+    let normalize = |name: &str| name.replace('\\', "/").trim_matches('/').to_owned();
+    let target = normalize(path);
+
+    status!("Fetching build manifest...");
+    let build_manifest = api::product::get_build_manifest(&client, product, version).await?;
+    status!("Fetching build manifest chunks...");
+    let build_manifest_chunks =
+        api::product::get_build_manifest_chunks(&client, product, version).await?;
+
+    let (selected, manifest_bytes, chunks_bytes) =
+        filter_manifests(&build_manifest[..], &build_manifest_chunks[..], |record| {
+            let file_name = normalize(&record.file_name);
+            let is_target = file_name == target;
+            let is_ancestor = record.is_directory() && target.starts_with(&format!("{file_name}/"));
+            let is_child = file_name.starts_with(&format!("{target}/"));
+            // Parent directories are kept too, so the target has somewhere to be written to
+            is_target || is_ancestor || is_child
+        });
+    if !selected.iter().any(|r| normalize(&r.file_name) == target) {
+        return Err(FreeCarnivalError::NotInManifest(path.to_owned()));
+    }
+
+    let download_size: usize = selected.iter().map(|r| r.size_in_bytes).sum();
+    if install_opts.info {
+        return Ok(format!(
+            "Files: {}\nDownload Size: {}",
+            selected.iter().filter(|r| !r.is_directory()).count(),
+            human_bytes(download_size as f64)
+        )
+        .into());
+    }
+
+    ensure_writable(out).await?;
+    let profile = install_opts.profile;
+    let mut stats = build_from_manifest(
+        client,
+        Arc::new(product.clone()),
+        Arc::new(version.os.to_owned()),
+        &manifest_bytes[..],
+        &chunks_bytes[..],
+        out.into(),
+        install_opts,
+        download_budget,
+        false,
+    )
+    .await?;
+    let timings = std::mem::take(&mut stats.timings);
+
+    for record in selected.iter().filter(|r| !r.is_directory()) {
+        let file_path = OsPath::from(out.join(&record.file_name));
+        if !verify_file_hash(&file_path, &record.sha)? {
+            return Err(FreeCarnivalError::Verify(format!(
+                "{} failed verification after download",
+                record.file_name
+            )));
+        }
+    }
+
+    Ok(DownloadReport {
+        message: format!(
+            "Fetched {} ({}) to {}",
+            path,
+            human_bytes(download_size as f64),
+            out.display()
+        ),
+        stats: Some(stats),
+        update: None,
+        timings: profile.then_some(timings),
+    })
+}
+
+/// Deletes a game's install directory, showing how many files were removed so far. Files
+/// matching `protected` are left in place, along with the directories containing them. With
+/// `dry_run`, what would be deleted is printed instead. Pressing Ctrl-C stops the removal with an
+/// `Interrupted` error, leaving the files that weren't deleted yet in place.
+pub(crate) async fn uninstall(
+    install_path: &PathBuf,
+    protected: Option<&ProtectedPaths>,
+    dry_run: bool,
+) -> tokio::io::Result<()> {
+    status!("Counting files...");
+    let total_files = count_files(install_path).await?;
+    let progress = if is_quiet() || dry_run {
+        ProgressBar::with_draw_target(Some(total_files), ProgressDrawTarget::hidden())
+    } else {
+        ProgressBar::new(total_files)
+    };
+    progress.set_style(
+        ProgressStyle::with_template("[{percent}%] {wide_bar:.cyan/blue} {pos}/{len} files")
+            .unwrap()
+            .progress_chars("##-"),
+    );
+
+    let removal = async {
+        if !remove_unprotected(install_path, install_path, protected, &progress, dry_run).await? {
+            if dry_run {
+                println!("Would remove {}", install_path.display());
+            } else {
+                tokio::fs::remove_dir(install_path).await?;
+            }
+        }
+        Ok(())
+    };
+    let result = tokio::select! {
+        result = removal => result,
+        _ = tokio::signal::ctrl_c() => Err(tokio::io::Error::new(
+            tokio::io::ErrorKind::Interrupted,
+            "Uninstall was cancelled",
+        )),
+    };
+    progress.finish_and_clear();
+
+    result
+}
+
+/// Moves an installed game from `from` to `to`, merging it with what's already in `to` when
+/// `force` is set. Across filesystems the files are copied first and only deleted from `from`
+/// once all of them were copied, so a failed move leaves the game where it was. Failing to
+/// delete them afterwards is only a warning, since the game is already playable from `to`.
+pub(crate) async fn move_install(
+    from: &Path,
+    to: &Path,
+    force: bool,
+) -> Result<(), FreeCarnivalError> {
+    if to.starts_with(from) {
+        return Err(std::io::Error::other(format!(
+            "{} is inside {}",
+            to.display(),
+            from.display()
+        ))
+        .into());
+    }
+
+    let mut to_existed = tokio::fs::try_exists(to).await?;
+    if to_existed {
+        let is_empty = tokio::fs::metadata(to).await?.is_dir()
+            && tokio::fs::read_dir(to).await?.next_entry().await?.is_none();
+        if is_empty {
+            tokio::fs::remove_dir(to).await?;
+            to_existed = false;
+        } else if !force {
+            return Err(FreeCarnivalError::DestinationNotEmpty(to.to_owned()));
+        }
+    }
+
+    let existing_dir = match to.parent() {
+        Some(parent) if !to_existed => {
+            tokio::fs::create_dir_all(parent).await?;
+            parent
+        }
+        _ => to,
+    };
+    if same_filesystem(from, existing_dir)? {
+        move_dir(&long_path(from), &long_path(to)).await?;
+        return Ok(());
+    }
+
+    status!(
+        "{} is on another filesystem, copying files...",
+        to.display()
+    );
+    if let Err(err) = copy_dir(&long_path(from), &long_path(to)).await {
+        if !to_existed {
+            let _ = tokio::fs::remove_dir_all(to).await;
+        }
+        return Err(err.into());
+    }
+    if let Err(err) = tokio::fs::remove_dir_all(&long_path(from)).await {
+        log::warn!(
+            "The files were copied to {}, but deleting them from {} failed: {err}",
+            to.display(),
+            from.display()
+        );
+    }
+
+    Ok(())
 }
 
 pub(crate) async fn check_updates(
@@ -138,7 +804,7 @@ pub(crate) async fn check_updates(
 ) -> tokio::io::Result<HashMap<String, String>> {
     let mut available_updates = HashMap::new();
     for (slug, info) in installed {
-        println!("Checking if {slug} has updates...");
+        status!("Checking if {slug} has updates...");
         let product = match library.collection.iter().find(|p| p.slugged_name == slug) {
             Some(p) => p,
             None => {
@@ -161,57 +827,64 @@ pub(crate) async fn check_updates(
     Ok(available_updates)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn update(
     client: reqwest::Client,
     library: &LibraryConfig,
     slug: &String,
     install_opts: InstallOpts,
+    download_budget: Arc<Semaphore>,
     install_info: &InstallInfo,
     selected_version: Option<&ProductVersion>,
-) -> tokio::io::Result<(String, Option<InstallInfo>)> {
+    force: bool,
+    verify_repair: bool,
+    backup: bool,
+) -> Result<(DownloadReport, Option<InstallInfo>), FreeCarnivalError> {
     let product = match library.collection.iter().find(|p| &p.slugged_name == slug) {
         Some(p) => p,
         None => {
-            return Ok((format!("Couldn't find {slug} in library"), None));
+            return Err(FreeCarnivalError::GameNotFound(slug.to_owned()));
         }
     };
     let version = match selected_version {
         Some(v) => v,
         None => {
-            println!("Fetching latest version...");
+            status!("Fetching latest version...");
             match product.get_latest_version(Some(&install_info.os)) {
                 Some(v) => v,
                 None => {
-                    return Ok((format!("Couldn't find the latest version of {slug}"), None));
+                    return Err(product.missing_build_error(Some(&install_info.os)));
                 }
             }
         }
     };
 
-    if install_info.version == version.version {
-        return Ok((format!("Build {version} is already installed"), None));
+    if version.os != install_info.os {
+        return Err(FreeCarnivalError::InstallBuild(format!(
+            "{slug} for {}",
+            install_info.os
+        )));
+    }
+    let reinstall = install_info.version == version.version;
+    if reinstall && !force {
+        return Ok((
+            format!("{slug} is already up to date with build {version}. Use --force to download it again.").into(),
+            None,
+        ));
     }
 
-    let old_manifest = read_build_manifest(&install_info.version, slug, "manifest").await?;
-
-    println!("Fetching {} build manifest...", version);
-    let new_manifest = match api::product::get_build_manifest(&client, product, version).await {
-        Ok(m) => m,
-        Err(err) => {
-            return Ok((format!("Failed to fetch build manifest: {:?}", err), None));
-        }
+    // Forcing the installed build again compares it against nothing, so every file is downloaded
+    let old_manifest = if reinstall {
+        vec![]
+    } else {
+        read_build_manifest(&install_info.version, slug, "manifest").await?
     };
+
+    status!("Fetching {} build manifest...", version);
+    let new_manifest = api::product::get_build_manifest(&client, product, version).await?;
     store_build_manifest(&new_manifest, &version.version, slug, "manifest").await?;
     let new_manifest_chunks =
-        match api::product::get_build_manifest_chunks(&client, product, version).await {
-            Ok(m) => m,
-            Err(err) => {
-                return Ok((
-                    format!("Failed to fetch build manifest chunks: {:?}", err),
-                    None,
-                ));
-            }
-        };
+        api::product::get_build_manifest_chunks(&client, product, version).await?;
     store_build_manifest(
         &new_manifest_chunks,
         &version.version,
@@ -279,7 +952,7 @@ pub(crate) async fn update(
             });
 
         let needed_space = disk_size - old_disk_size;
-        println!("{}", needed_space);
+        status!("{}", needed_space);
 
         let mut buf = String::new();
         buf.push_str(&format!("Download Size: {}", human_bytes(download_size)));
@@ -289,30 +962,610 @@ pub(crate) async fn update(
             human_bytes(needed_space.abs())
         ));
         buf.push_str(&format!("\nTotal Disk Size: {}", human_bytes(disk_size)));
-        return Ok((buf, None));
+        return Ok((buf.into(), None));
     }
 
-    let product_arc = Arc::new(product.clone());
-    let version_arc = Arc::new(version.os.to_owned());
-    build_from_manifest(
-        client,
-        product_arc,
-        version_arc,
-        &delta_manifest[..],
-        &delta_manifest_chunks[..],
-        OsPath::from(&install_info.install_path),
-        install_opts,
+    ensure_writable(&install_info.install_path).await?;
+
+    // Saves and settings that already exist on disk are left untouched by the update
+    let settings = SettingsConfig::load()?;
+    let protected = ProtectedPaths::new(&settings.protected_paths);
+    let (changes, delta_manifest, delta_manifest_chunks) =
+        filter_manifests(&delta_manifest[..], &delta_manifest_chunks[..], |record| {
+            let protect = protected.matches(&record.file_name)
+                && install_info.install_path.join(&record.file_name).exists();
+            if protect {
+                status!("Keeping protected file {}", record.file_name);
+            }
+            !protect
+        });
+
+    let backup_path = if backup {
+        let backup_path = update_backup_path(&install_info.install_path);
+        if tokio::fs::try_exists(&backup_path).await? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} is left from an update that didn't finish. Move its files back to {} or delete it.",
+                    backup_path.display(),
+                    install_info.install_path.display()
+                ),
+            )
+            .into());
+        }
+        status!("Backing up the files this update replaces...");
+        if let Err(err) = backup_files(&install_info.install_path, &backup_path, &changes).await {
+            // The files moved before the failure are put back, leaving the install as it was
+            if tokio::fs::try_exists(&backup_path).await.unwrap_or(true) {
+                if let Err(restore_err) = move_dir(
+                    &long_path(&backup_path),
+                    &long_path(&install_info.install_path),
+                )
+                .await
+                {
+                    println!(
+                        "Failed to restore the files of {slug} that were backed up, they were kept in {}: {restore_err}",
+                        backup_path.display()
+                    );
+                }
+            }
+            return Err(err.into());
+        }
+        Some(backup_path)
+    } else {
+        None
+    };
+
+    let profile = install_opts.profile;
+    let result: Result<_, FreeCarnivalError> = async {
+        let product_arc = Arc::new(product.clone());
+        let version_arc = Arc::new(version.os.to_owned());
+        let stats = build_from_manifest(
+            client.clone(),
+            product_arc.clone(),
+            version_arc.clone(),
+            &delta_manifest[..],
+            &delta_manifest_chunks[..],
+            OsPath::from(&install_info.install_path),
+            install_opts.clone(),
+            download_budget.clone(),
+            false,
+        )
+        .await?;
+
+        if verify_repair {
+            let mut to_check: Vec<BuildManifestRecord> = changes
+                .iter()
+                .filter(|r| !r.is_directory() && r.tag != Some(ChangeTag::Removed))
+                .cloned()
+                .collect();
+            let mut repaired = vec![];
+            let mut attempts = 0;
+            loop {
+                status!("Verifying {} updated file(s)...", to_check.len());
+                let corrupted = find_corrupted(&install_info.install_path, &to_check).await?;
+                if corrupted.is_empty() {
+                    break;
+                }
+                if attempts == *MAX_REPAIR_ATTEMPTS {
+                    return Err(FreeCarnivalError::Verify(format!(
+                        "These files of {slug} are still corrupted after {attempts} repair attempt(s):\n{}",
+                        corrupted.join("\n")
+                    )));
+                }
+
+                attempts += 1;
+                status!("Repairing {} corrupted file(s)...", corrupted.len());
+                let (_, repair_manifest, repair_manifest_chunks) =
+                    filter_manifests(&delta_manifest[..], &delta_manifest_chunks[..], |r| {
+                        corrupted.contains(&r.file_name)
+                    });
+                build_from_manifest(
+                    client.clone(),
+                    product_arc.clone(),
+                    version_arc.clone(),
+                    &repair_manifest[..],
+                    &repair_manifest_chunks[..],
+                    OsPath::from(&install_info.install_path),
+                    install_opts.clone(),
+                    download_budget.clone(),
+                    false,
+                )
+                .await?;
+                to_check.retain(|r| corrupted.contains(&r.file_name));
+                for file_name in corrupted {
+                    if !repaired.contains(&file_name) {
+                        repaired.push(file_name);
+                    }
+                }
+            }
+            for file_name in repaired {
+                println!("Repaired {file_name}");
+            }
+        }
+        Ok(stats)
+    }
+    .await;
+    let stats = match (result, backup_path) {
+        (Ok(stats), Some(backup_path)) => {
+            // The update is done, a backup that can't be deleted only takes up space
+            if let Err(err) = tokio::fs::remove_dir_all(&backup_path).await {
+                log::warn!(
+                    "Failed to delete the backup in {}: {err}. Delete it before updating with --backup again.",
+                    backup_path.display()
+                );
+            }
+            stats
+        }
+        (Ok(stats), None) => stats,
+        (Err(err), Some(backup_path)) => {
+            status!("Restoring {slug} {}...", install_info.version);
+            if let Err(restore_err) =
+                restore_backup(&install_info.install_path, &backup_path, &changes).await
+            {
+                println!(
+                    "Failed to restore the previous files of {slug}, they were kept in {}: {restore_err}",
+                    backup_path.display()
+                );
+            }
+            return Err(err);
+        }
+        (Err(err), None) => return Err(err),
+    };
+
+    let count = |tag: ChangeTag| {
+        changes
+            .iter()
+            .filter(|r| !r.is_directory() && r.tag.as_ref() == Some(&tag))
+            .count()
+    };
+    let summary = UpdateSummary {
+        added: count(ChangeTag::Added),
+        changed: count(ChangeTag::Modified),
+        removed: count(ChangeTag::Removed),
+        downloaded_bytes: stats.total_bytes,
+    };
+    let mut stats = stats;
+    let timings = std::mem::take(&mut stats.timings);
+
+    let install_info = InstallInfo {
+        version: version.version.to_owned(),
+        os: version.os.to_owned(),
+        notified_version: None,
+        ..install_info.clone()
+    };
+    let report = DownloadReport {
+        message: format!("Updated {slug} successfully."),
+        stats: Some(stats),
+        update: Some(summary),
+        timings: profile.then_some(timings),
+    };
+    Ok((report, Some(install_info)))
+}
+
+/// Where `update --backup` keeps the files an update replaces, next to the install directory.
+fn update_backup_path(install_path: &Path) -> PathBuf {
+    install_path.with_file_name(format!(
+        ".{}.backup",
+        install_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    ))
+}
+
+/// Moves the files of `changes` that exist in `install_path` to `backup_path`. The update
+/// deletes them before writing the new ones anyway, so nothing needs to be copied.
+async fn backup_files(
+    install_path: &Path,
+    backup_path: &Path,
+    changes: &[BuildManifestRecord],
+) -> tokio::io::Result<()> {
+    for record in changes.iter().filter(|r| !r.is_directory()) {
+        let file_path = long_path(&install_path.join(&record.file_name));
+        if !file_path.is_file() {
+            continue;
+        }
+        let backup_file_path = long_path(&backup_path.join(&record.file_name));
+        if let Some(parent) = backup_file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        match tokio::fs::rename(&file_path, &backup_file_path).await {
+            Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+                tokio::fs::copy(&file_path, &backup_file_path).await?;
+                tokio::fs::remove_file(&file_path).await?;
+            }
+            result => result?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Undoes an update that failed: the files it wrote are deleted and the ones moved to
+/// `backup_path` by `backup_files` are put back.
+async fn restore_backup(
+    install_path: &Path,
+    backup_path: &Path,
+    changes: &[BuildManifestRecord],
+) -> tokio::io::Result<()> {
+    for record in changes.iter().filter(|r| !r.is_directory()) {
+        match tokio::fs::remove_file(long_path(&install_path.join(&record.file_name))).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+
+    move_dir(&long_path(backup_path), &long_path(install_path)).await
+}
+
+/// Hashes the files of `records` inside `install_path`, returning the names of the ones that
+/// don't match the manifest.
+async fn find_corrupted(
+    install_path: &Path,
+    records: &[BuildManifestRecord],
+) -> tokio::io::Result<Vec<String>> {
+    let hash_jobs = Arc::new(Semaphore::new(*DEFAULT_VERIFY_JOBS));
+    let mut handles = vec![];
+    for record in records {
+        let file_path = OsPath::from(install_path.join(&record.file_name));
+        let file_name = record.file_name.to_owned();
+        let sha = record.sha.to_owned();
+        let permit = hash_jobs.clone().acquire_owned().await.unwrap();
+        handles.push(tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            match verify_file_hash(&file_path, &sha) {
+                Ok(true) => None,
+                _ => Some(file_name),
+            }
+        }));
+    }
+
+    let mut corrupted = vec![];
+    for handle in handles {
+        corrupted.extend(handle.await?);
+    }
+    Ok(corrupted)
+}
+
+/// Checks every chunk of an installed game against its build manifest, downloading only the
+/// chunks that don't match and writing them in place. Files whose chunks can't be checked one by
+/// one (e.g. missing files) are downloaded whole.
+pub(crate) async fn repair(
+    client: reqwest::Client,
+    library: &LibraryConfig,
+    slug: &String,
+    install_opts: InstallOpts,
+    download_budget: Arc<Semaphore>,
+    install_info: &InstallInfo,
+) -> Result<String, FreeCarnivalError> {
+    let product = match library.collection.iter().find(|p| &p.slugged_name == slug) {
+        Some(p) => p,
+        None => return Err(FreeCarnivalError::GameNotFound(slug.to_owned())),
+    };
+    let build_version = match product
+        .version
+        .iter()
+        .find(|v| v.version == install_info.version && v.os == install_info.os)
+    {
+        Some(v) => v,
+        None => {
+            return Err(FreeCarnivalError::InstallBuild(format!(
+                "{slug} {} for {}",
+                install_info.version, install_info.os
+            )))
+        }
+    };
+
+    status!("Fetching build manifest...");
+    let build_manifest = api::product::get_build_manifest(&client, product, build_version).await?;
+    store_build_manifest(&build_manifest, &build_version.version, slug, "manifest").await?;
+    status!("Fetching build manifest chunks...");
+    let build_manifest_chunks =
+        api::product::get_build_manifest_chunks(&client, product, build_version).await?;
+    store_build_manifest(
+        &build_manifest_chunks,
+        &build_version.version,
+        slug,
+        "manifest_chunks",
     )
     .await?;
 
-    let install_info = InstallInfo::new(
-        install_info.install_path.to_owned(),
-        version.version.to_owned(),
-        version.os.to_owned(),
+    let (records, _, _) = filter_manifests(&build_manifest[..], &[], |r| {
+        !r.is_directory() && !install_info.deferred_files.contains(&r.file_name)
+    });
+    let mut file_chunks: HashMap<String, Vec<BuildManifestChunksRecord>> = HashMap::new();
+    let mut chunks_rdr = csv::Reader::from_reader(&build_manifest_chunks[..]);
+    for record in chunks_rdr.byte_records() {
+        let record = record
+            .expect("Failed to get byte record")
+            .deserialize::<BuildManifestChunksRecord>(None)
+            .expect("Failed to deserialize build manifest chunks");
+        file_chunks
+            .entry(record.file_path.to_owned())
+            .or_default()
+            .push(record);
+    }
+
+    status!("Checking chunks of {} file(s)...", records.len());
+    let hash_jobs = Arc::new(Semaphore::new(*DEFAULT_VERIFY_JOBS));
+    let mut handles = vec![];
+    for record in records {
+        let mut chunks = file_chunks.remove(&record.file_name).unwrap_or_default();
+        chunks.sort_by_key(|chunk| chunk.id);
+        let file_path = OsPath::from(install_info.install_path.join(&record.file_name));
+        let permit = hash_jobs.clone().acquire_owned().await.unwrap();
+        handles.push(tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let bad = find_bad_chunks(&file_path, record.size_in_bytes as u64, &chunks);
+            (record, chunks, bad)
+        }));
+    }
+
+    let mut bad_chunks = vec![];
+    let mut whole_files = vec![];
+    for handle in handles {
+        let (record, chunks, bad) = handle.await.expect("Chunk check panicked");
+        match bad? {
+            Some(ids) => bad_chunks.extend(
+                chunks
+                    .into_iter()
+                    .filter(|chunk| ids.contains(&chunk.id))
+                    .map(|chunk| (record.size_in_bytes as u64, chunk)),
+            ),
+            None => whole_files.push(record.file_name),
+        }
+    }
+    if bad_chunks.is_empty() && whole_files.is_empty() {
+        return Ok(format!("{slug} has no corrupted chunks."));
+    }
+
+    status!("Downloading {} corrupted chunk(s)...", bad_chunks.len());
+    let session_generation = tokio::sync::Mutex::new(0);
+    let retries = AtomicU64::new(0);
+    let stall_timeout = Duration::from_secs(install_opts.timeout_per_chunk);
+    let rate_limit = install_opts.limit_rate.map(RateLimiter::new);
+    let max_chunk_size = *MAX_CHUNK_SIZE as u64;
+    let mut repaired_files = HashSet::new();
+    let mut repaired_chunks = 0;
+    for (size, chunk) in bad_chunks {
+        let permit = download_budget.acquire().await.unwrap();
+        let bytes = download_chunk_refreshing_session(
+            &client,
+            product,
+            &install_info.os,
+            &chunk.sha,
+            &session_generation,
+            &retries,
+            stall_timeout,
+            install_opts.retries,
+            rate_limit.as_ref(),
+        )
+        .await?;
+        drop(permit);
+        if !verify_chunk(&bytes, chunk_sha(&chunk.sha))? {
+            return Err(FreeCarnivalError::Verify(format!(
+                "Chunk {} of {} is still corrupted after downloading it again",
+                chunk.id, chunk.file_path
+            )));
+        }
+
+        let offset = u64::from(chunk.id) * max_chunk_size;
+        // The chunk is only written in place if it's as long as the one it replaces
+        if bytes.len() as u64 != (size - offset).min(max_chunk_size) {
+            if !whole_files.contains(&chunk.file_path) {
+                whole_files.push(chunk.file_path);
+            }
+            continue;
+        }
+        let file_path = OsPath::from(install_info.install_path.join(&chunk.file_path));
+        write_chunk_at(&file_path, offset, &bytes).await?;
+        repaired_chunks += 1;
+        repaired_files.insert(chunk.file_path);
+    }
+
+    if !whole_files.is_empty() {
+        status!("Downloading {} file(s) again...", whole_files.len());
+        let (_, manifest, manifest_chunks) =
+            filter_manifests(&build_manifest[..], &build_manifest_chunks[..], |r| {
+                whole_files.contains(&r.file_name)
+            });
+        build_from_manifest(
+            client,
+            Arc::new(product.clone()),
+            Arc::new(install_info.os.to_owned()),
+            &manifest[..],
+            &manifest_chunks[..],
+            OsPath::from(&install_info.install_path),
+            install_opts,
+            download_budget,
+            false,
+        )
+        .await?;
+    }
+
+    status!("Verifying repaired files...");
+    let (repaired, _, _) = filter_manifests(&build_manifest[..], &[], |r| {
+        repaired_files.contains(&r.file_name) || whole_files.contains(&r.file_name)
+    });
+    let corrupted = find_corrupted(&install_info.install_path, &repaired).await?;
+    if !corrupted.is_empty() {
+        return Err(FreeCarnivalError::Verify(format!(
+            "These files of {slug} are still corrupted after the repair:\n{}",
+            corrupted.join("\n")
+        )));
+    }
+
+    Ok(format!(
+        "Repaired {repaired_chunks} chunk(s) of {slug} and downloaded {} file(s) again.",
+        whole_files.len()
+    ))
+}
+
+/// Checks if a newer version of this program was released, replacing the running binary with
+/// it when `download` is set.
+pub(crate) async fn self_update(
+    client: &reqwest::Client,
+    download: bool,
+) -> Result<String, FreeCarnivalError> {
+    status!("Checking for new releases...");
+    let release = api::release::get_latest_release(client).await?;
+    let latest_version = release.tag_name.trim_start_matches('v');
+    if compare_versions(latest_version, &PROJECT_VERSION) != Ordering::Greater {
+        return Ok(format!("{} is up to date.", *PROJECT_NAME));
+    }
+
+    let update_available = format!(
+        "{} {latest_version} is available (installed: {}).",
+        *PROJECT_NAME, *PROJECT_VERSION
     );
-    Ok((format!("Updated {slug} successfully."), Some(install_info)))
+    if !download {
+        return Ok(format!(
+            "{update_available} Run `self-update --download` to update, or get it from {}",
+            release.html_url
+        ));
+    }
+
+    // Only plain binaries can replace the running one, archives have to be installed by hand
+    let asset = release.assets.iter().find(|asset| {
+        let name = asset.name.to_lowercase();
+        name.contains(std::env::consts::OS)
+            && arch_names().iter().any(|arch| name.contains(arch))
+            && ![".zip", ".tar.gz", ".tgz", ".tar.xz", ".sha256"]
+                .iter()
+                .any(|ext| name.ends_with(ext))
+    });
+    let asset = match asset {
+        Some(asset) => asset,
+        None => {
+            return Ok(format!(
+                "{update_available} There's no {} {} binary to download, get it from {}",
+                std::env::consts::OS,
+                std::env::consts::ARCH,
+                release.html_url
+            ))
+        }
+    };
+    let expected_sha = match published_sha256(client, &release, asset).await? {
+        Some(sha) => sha,
+        None => {
+            return Ok(format!(
+            "{update_available} {} has no published SHA-256 to check it against, get it from {}",
+            asset.name, release.html_url
+        ))
+        }
+    };
+
+    status!("Downloading {}...", asset.name);
+    let binary = api::release::download_asset(client, &asset.browser_download_url).await?;
+    if !format!("{:x}", Sha256::digest(&binary)).eq_ignore_ascii_case(&expected_sha) {
+        return Err(FreeCarnivalError::Verify(format!(
+            "{} doesn't match its published SHA-256, the running binary was left as is",
+            asset.name
+        )));
+    }
+    let current_exe = std::env::current_exe()?;
+    let new_exe = current_exe.with_extension("new");
+    tokio::fs::write(&new_exe, binary).await?;
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(&new_exe, std::fs::Permissions::from_mode(0o755)).await?;
+    }
+    // Windows can't overwrite a running executable, but it can rename it
+    #[cfg(target_os = "windows")]
+    tokio::fs::rename(&current_exe, current_exe.with_extension("old")).await?;
+    tokio::fs::rename(&new_exe, &current_exe).await?;
+
+    Ok(format!("Updated {} to {latest_version}.", *PROJECT_NAME))
+}
+
+/// Names release assets use for the architecture this program was built for.
+fn arch_names() -> &'static [&'static str] {
+    match std::env::consts::ARCH {
+        "x86_64" => &["x86_64", "amd64", "x64"],
+        "aarch64" => &["aarch64", "arm64"],
+        "x86" => &["i686", "i386"],
+        _ => &[std::env::consts::ARCH],
+    }
+}
+
+/// The SHA-256 published for `asset`, either by GitHub itself, in a `<asset>.sha256` file, or
+/// in a `SHA256SUMS`/`checksums.txt` file of `release`.
+async fn published_sha256(
+    client: &reqwest::Client,
+    release: &Release,
+    asset: &ReleaseAsset,
+) -> Result<Option<String>, FreeCarnivalError> {
+    if let Some(sha) = asset
+        .digest
+        .as_deref()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+    {
+        return Ok(Some(sha.to_owned()));
+    }
+
+    let sums = release.assets.iter().find(|sums| {
+        let name = sums.name.to_lowercase();
+        name == format!("{}.sha256", asset.name.to_lowercase())
+            || name == "sha256sums"
+            || name == "sha256sums.txt"
+            || name == "checksums.txt"
+    });
+    let sums = match sums {
+        Some(sums) => sums,
+        None => return Ok(None),
+    };
+    let sums = api::release::download_asset(client, &sums.browser_download_url).await?;
+    // Lines are `<sha>  <file name>`, with a `*` before binary files. A `<asset>.sha256` file
+    // can also have just the hash.
+    let sha = String::from_utf8_lossy(&sums).lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let sha = fields.next()?;
+        match fields.next() {
+            Some(name) if name.trim_start_matches('*') != asset.name => None,
+            _ => Some(sha.to_owned()),
+        }
+    });
+    Ok(sha)
 }
 
+/// Installs winetricks `verbs` in the WINE prefix. Returns whether all of them were installed.
+#[cfg(not(target_os = "windows"))]
+pub(crate) async fn winetricks(
+    verbs: &[String],
+    wine_bin: Option<&PathBuf>,
+    wine_prefix: Option<&PathBuf>,
+) -> tokio::io::Result<bool> {
+    let winetricks = match find_in_path("winetricks") {
+        Some(path) => path,
+        None => {
+            println!(
+                "winetricks isn't installed, skipping {}. Install it to set up these verbs.",
+                verbs.join(", ")
+            );
+            return Ok(false);
+        }
+    };
+
+    status!("Running winetricks {}...", verbs.join(" "));
+    let mut command = tokio::process::Command::new(winetricks);
+    command.arg("-q").args(verbs);
+    if let Some(wine_bin) = wine_bin {
+        command.env("WINE", wine_bin);
+    }
+    if let Some(wine_prefix) = wine_prefix {
+        command.env("WINEPREFIX", wine_prefix);
+    }
+    let status = command.status().await?;
+    if !status.success() {
+        println!("winetricks exited with: {}", status);
+    }
+
+    Ok(status.success())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn launch(
     client: &reqwest::Client,
     product: &Product,
@@ -321,21 +1574,32 @@ pub(crate) async fn launch(
     #[cfg(not(target_os = "windows"))] wine_bin: Option<PathBuf>,
     #[cfg(not(target_os = "windows"))] wine_prefix: Option<PathBuf>,
     wrapper: Option<PathBuf>,
+    #[cfg(target_os = "linux")] gamescope: Option<&str>,
+    #[cfg(target_os = "linux")] gpu: Option<&str>,
+    hooks: &HookOpts,
+    check_deps: bool,
+    print_env: bool,
+    game_args: &[String],
 ) -> tokio::io::Result<Option<ExitStatus>> {
     let os = &install_info.os;
 
     #[cfg(not(target_os = "windows"))]
     let wine_bin = match os {
+        BuildOs::Windows if no_wine => None,
         BuildOs::Windows => match wine_bin {
             Some(wine_bin) => Some(wine_bin),
-            None => {
-                if !no_wine {
-                    println!("You need to set --wine-bin to run Windows games");
+            None => match find_in_path("wine") {
+                Some(wine_bin) => {
+                    status!("Using {} to run this Windows game", wine_bin.display());
+                    Some(wine_bin)
+                }
+                None => {
+                    println!(
+                        "You need to set --wine or --runner to run Windows games, no wine was found in PATH"
+                    );
                     return Ok(None);
-                } else {
-                    None
                 }
-            }
+            },
         },
         _ => None,
     };
@@ -368,6 +1632,10 @@ pub(crate) async fn launch(
     };
     let install_path = OsPath::from(&install_info.install_path);
 
+    // The exe path in the game details is the Windows one, so native builds only use it if
+    // their files have it too
+    let exe_path = exe_path
+        .filter(|path| os == &BuildOs::Windows || install_path.join(path).to_pathbuf().is_file());
     let exe = match exe_path {
         Some(path) => install_path.join(path).to_pathbuf(),
         None => match os {
@@ -378,6 +1646,8 @@ pub(crate) async fn launch(
                     return Ok(None);
                 }
             },
+            // Apps run the executable named in their Info.plist, other builds the first
+            // Mach-O program found
             #[cfg(target_os = "macos")]
             BuildOs::Mac => match find_app_recursive(&install_info.install_path).await {
                 Some(app) => {
@@ -392,118 +1662,605 @@ pub(crate) async fn launch(
                         }
                     }
                 }
-                None => {
-                    println!("Couldn't find a suitable app...");
-                    return Ok(None);
-                }
+                None => match find_mach_o_recursive(&install_info.install_path).await {
+                    Some(exe) => exe,
+                    None => {
+                        println!("Couldn't find a suitable app...");
+                        return Ok(None);
+                    }
+                },
             },
             #[cfg(not(target_os = "macos"))]
             BuildOs::Mac => {
                 println!("You can only launch macOS games on macOS");
                 return Ok(None);
             }
+            #[cfg(target_os = "linux")]
+            BuildOs::Linux => match find_elf_recursive(&install_info.install_path).await {
+                Some(exe) => exe,
+                None => {
+                    println!("Couldn't find a suitable program...");
+                    return Ok(None);
+                }
+            },
+            #[cfg(not(target_os = "linux"))]
             BuildOs::Linux => {
-                println!("We don't support launching Linux games yet...");
+                println!("You can only launch Linux games on Linux");
                 return Ok(None);
             }
         },
     };
-    println!("{} was selected", exe.display());
+    status!("{} was selected", exe.display());
 
-    #[cfg(not(target_os = "windows"))]
-    let should_use_wine = (os == &BuildOs::Windows) && !no_wine;
-    #[cfg(target_os = "windows")]
-    let should_use_wine = false;
-    #[cfg(target_os = "windows")]
-    let wine_bin: Option<PathBuf> = None;
-    let wrapper_string = if wrapper.is_some() {
-        wrapper.unwrap_or_default().to_str().unwrap().to_owned()
-    } else {
-        "".to_owned()
-    };
-    let wrapper_vec = if !wrapper_string.is_empty() {
-        split(&wrapper_string.to_owned()).unwrap()
-    } else {
-        Vec::<String>::new()
-    };
-    let binary = if wrapper_vec.len() > 0 {
-        wrapper_vec[0].to_owned()
-    } else {
-        if should_use_wine {
-            wine_bin.unwrap().to_str().unwrap().to_owned()
-        } else {
-            exe.to_str().unwrap().to_owned()
+    // Native builds run directly, but their executable bit can be lost (e.g. when copied from
+    // another filesystem)
+    #[cfg(unix)]
+    if os != &BuildOs::Windows {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = tokio::fs::metadata(&exe).await?.permissions();
+        if permissions.mode() & 0o111 == 0 {
+            status!("Marking {} as executable", exe.display());
+            permissions.set_mode(permissions.mode() | 0o755);
+            tokio::fs::set_permissions(&exe, permissions).await?;
         }
-    };
+    }
 
-    let mut command = tokio::process::Command::new(binary);
-    if wrapper_vec.len() > 1 {
-        for val in wrapper_vec.iter().skip(1) {
-            command.arg(val);
+    let launch_command = match launch_command(
+        install_info,
+        &exe,
+        #[cfg(not(target_os = "windows"))]
+        wine_bin.as_deref(),
+        #[cfg(not(target_os = "windows"))]
+        wine_prefix.as_deref(),
+        wrapper.as_deref(),
+        #[cfg(target_os = "linux")]
+        gamescope,
+        #[cfg(target_os = "linux")]
+        gpu,
+        game_args,
+    ) {
+        Ok(launch_command) => launch_command,
+        Err(err) => {
+            println!("{err}");
+            return Ok(None);
         }
     };
 
-    if !wrapper_string.is_empty() || should_use_wine {
-        command.arg(exe.to_str().unwrap().to_owned());
-    };
-    // TODO:
-    // Handle cwd and launch args. Since I don't have games that have these I don't have a
-    // reliable way to test...
+    if print_env {
+        let quote = |value: &str| {
+            shlex::try_quote(value)
+                .expect("Launch arguments can't contain NUL bytes")
+                .into_owned()
+        };
+        let command: Vec<String> = launch_command.argv.iter().map(|arg| quote(arg)).collect();
+        println!("CARNIVAL_COMMAND={}", quote(&command.join(" ")));
+        println!("CARNIVAL_CWD={}", quote(&install_path.to_string()));
+        for (name, value) in &launch_command.env {
+            println!("export {name}={}", quote(value));
+        }
+        return Ok(None);
+    }
+
+    let missing: Vec<&String> = launch_command
+        .programs
+        .iter()
+        .filter(|program| find_program(program).is_none())
+        .collect();
+    for program in &missing {
+        println!("Missing dependency {program}. {}", install_hint(program));
+    }
     #[cfg(not(target_os = "windows"))]
-    if let Some(wine_prefix) = wine_prefix {
-        command.env("WINEPREFIX", wine_prefix);
+    let wine_32bit_hint = (launch_command.uses_wine && is_32bit_exe(&exe).await).then(|| {
+        format!(
+            "{} is a 32-bit game, which needs a WINE build that supports 32-bit applications \
+            (e.g. a WoW64 build or your distribution's 32-bit WINE packages).",
+            exe.display()
+        )
+    });
+    if check_deps {
+        #[cfg(not(target_os = "windows"))]
+        if let Some(hint) = &wine_32bit_hint {
+            println!("{hint}");
+        }
+        if missing.is_empty() {
+            println!("All dependencies were found");
+        }
+        return Ok(None);
     }
-    println!("{} is the CWD", install_path);
+    if !missing.is_empty() {
+        println!("Launching anyway, but the game will likely fail to start");
+    }
+
+    let mut command = launch_command.command();
+    status!("{} is the CWD", install_path);
+
+    let hook_timeout = Duration::from_secs(hooks.hook_timeout);
+    if let Some(pre_launch) = &hooks.pre_launch {
+        if !run_hook("pre-launch", pre_launch, &install_path, hook_timeout).await {
+            println!("Not launching because the pre-launch hook failed");
+            return Ok(None);
+        }
+    }
+
     let mut child = command.current_dir(install_path.to_pathbuf()).spawn()?;
 
     let status = child.wait().await?;
+    // Only shown when it could explain why the game didn't run
+    #[cfg(not(target_os = "windows"))]
+    if let Some(hint) = wine_32bit_hint.filter(|_| !status.success()) {
+        println!("{hint}");
+    }
+
+    if let Some(post_exit) = &hooks.post_exit {
+        // The game already exited, so a failing hook shouldn't hide its exit status
+        run_hook("post-exit", post_exit, &install_path, hook_timeout).await;
+    }
 
     Ok(Some(status))
 }
 
-pub(crate) async fn verify(slug: &String, install_info: &InstallInfo) -> tokio::io::Result<bool> {
-    let mut handles: Vec<JoinHandle<bool>> = vec![];
+/// What `launch` runs: the game's command line, the environment variables set for it and the
+/// programs it depends on besides the game itself.
+#[derive(Debug)]
+struct LaunchCommand {
+    argv: Vec<String>,
+    env: Vec<(String, String)>,
+    programs: Vec<String>,
+    uses_wine: bool,
+}
 
-    let build_manifest = read_build_manifest(&install_info.version, slug, "manifest").await?;
-    let mut build_manifest_rdr = csv::Reader::from_reader(&build_manifest[..]);
-    let build_manifest_byte_records = build_manifest_rdr.byte_records();
+impl LaunchCommand {
+    fn command(&self) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new(&self.argv[0]);
+        command.args(&self.argv[1..]);
+        // TODO:
+        // Handle cwd and launch args. Since I don't have games that have these I don't have a
+        // reliable way to test...
+        command.envs(self.env.iter().map(|(name, value)| (name, value)));
+        command
+    }
+}
 
-    for record in build_manifest_byte_records {
-        let mut record = record.expect("Failed to get byte record");
-        record.push_field(b"");
-        let record = record
-            .deserialize::<BuildManifestRecord>(None)
-            .expect("Failed to deserialize build manifest");
+/// Builds the command that runs `exe`. Windows builds are run through `wine_bin` (when there is
+/// one) with `wine_prefix` as their WINE prefix, while native builds are run directly.
+#[allow(clippy::too_many_arguments)]
+fn launch_command(
+    install_info: &InstallInfo,
+    exe: &Path,
+    #[cfg(not(target_os = "windows"))] wine_bin: Option<&Path>,
+    #[cfg(not(target_os = "windows"))] wine_prefix: Option<&Path>,
+    wrapper: Option<&Path>,
+    #[cfg(target_os = "linux")] gamescope: Option<&str>,
+    #[cfg(target_os = "linux")] gpu: Option<&str>,
+    game_args: &[String],
+) -> Result<LaunchCommand, String> {
+    #[cfg(not(target_os = "windows"))]
+    let wine_bin = wine_bin.filter(|_| install_info.os == BuildOs::Windows);
+    #[cfg(target_os = "windows")]
+    let wine_bin: Option<&Path> = None;
 
-        if record.is_directory() {
-            continue;
+    let mut argv: Vec<String> = vec![];
+    let mut programs: Vec<String> = vec![];
+    #[cfg(target_os = "linux")]
+    if let Some(gamescope_args) = gamescope {
+        programs.push("gamescope".to_owned());
+        argv.push("gamescope".to_owned());
+        match split(gamescope_args) {
+            Some(args) => argv.extend(args),
+            None => {
+                return Err(format!(
+                    "Failed to parse gamescope arguments: {gamescope_args}"
+                ))
+            }
+        }
+        argv.push("--".to_owned());
+    }
+    if let Some(wrapper) = wrapper {
+        let wrapper_string = wrapper.to_str().unwrap();
+        match split(wrapper_string) {
+            Some(args) => {
+                programs.extend(args.first().cloned());
+                argv.extend(args)
+            }
+            None => return Err(format!("Failed to parse wrapper: {wrapper_string}")),
         }
+    }
+    if let Some(wine_bin) = wine_bin {
+        let wine_bin = wine_bin.to_str().unwrap().to_owned();
+        programs.push(wine_bin.to_owned());
+        argv.push(wine_bin);
+    }
+    argv.push(exe.to_str().unwrap().to_owned());
+    argv.extend(game_args.iter().cloned());
 
-        let file_path = OsPath::from(install_info.install_path.join(&record.file_name));
-        if !tokio::fs::try_exists(&file_path).await? {
-            println!("{} is missing", record.file_name);
-            return Ok(false);
+    #[cfg_attr(target_os = "windows", allow(unused_mut))]
+    let mut env: Vec<(String, String)> = install_info
+        .launch_options
+        .env
+        .iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+    #[cfg(not(target_os = "windows"))]
+    if let Some(wine_prefix) = wine_prefix.filter(|_| wine_bin.is_some()) {
+        env.push((
+            "WINEPREFIX".to_owned(),
+            wine_prefix.to_string_lossy().into_owned(),
+        ));
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(gpu) = gpu {
+        env.extend(
+            gpu_env(gpu)
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value)),
+        );
+    }
+
+    Ok(LaunchCommand {
+        argv,
+        env,
+        programs,
+        uses_wine: wine_bin.is_some(),
+    })
+}
+
+/// Runs a launch hook in `cwd`, killing it if it's still running after `hook_timeout`.
+/// Returns whether the hook ran successfully.
+async fn run_hook(name: &str, hook: &str, cwd: &OsPath, hook_timeout: Duration) -> bool {
+    let argv = match split(hook) {
+        Some(argv) if !argv.is_empty() => argv,
+        _ => {
+            println!("Failed to parse {name} hook: {hook}");
+            return false;
+        }
+    };
+
+    status!("Running {name} hook...");
+    let mut child = match tokio::process::Command::new(&argv[0])
+        .args(&argv[1..])
+        .current_dir(cwd.to_pathbuf())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            println!("Failed to run {name} hook: {:?}", err);
+            return false;
         }
+    };
+
+    match timeout(hook_timeout, child.wait()).await {
+        Ok(Ok(status)) if status.success() => true,
+        Ok(Ok(status)) => {
+            println!("{name} hook exited with: {}", status);
+            false
+        }
+        Ok(Err(err)) => {
+            println!("Failed to wait for {name} hook: {:?}", err);
+            false
+        }
+        Err(_) => {
+            println!(
+                "{name} hook timed out after {}s and was killed",
+                hook_timeout.as_secs()
+            );
+            if let Err(err) = child.kill().await {
+                println!("Failed to kill {name} hook: {:?}", err);
+            }
+            false
+        }
+    }
+}
+
+/// Downloads and stores the build manifest of the exact version and OS that is installed.
+async fn fetch_installed_manifest(
+    client: &reqwest::Client,
+    slug: &String,
+    install_info: &InstallInfo,
+) -> Result<Vec<u8>, FreeCarnivalError> {
+    let library = LibraryConfig::load()?;
+    let (product, build_version) = find_installed_build(&library, slug, install_info)?;
+
+    status!("Fetching build manifest...");
+    let build_manifest = api::product::get_build_manifest(client, product, build_version).await?;
+    store_build_manifest(&build_manifest, &build_version.version, slug, "manifest").await?;
+    Ok(build_manifest.to_vec())
+}
+
+/// Downloads and stores both build manifests of the version and OS that is installed, which
+/// `update` and `complete` read back. Used for games that weren't installed by this program,
+/// e.g. after `import`.
+pub(crate) async fn store_installed_manifests(
+    client: &reqwest::Client,
+    slug: &String,
+    install_info: &InstallInfo,
+) -> Result<(), FreeCarnivalError> {
+    let library = LibraryConfig::load()?;
+    let (product, build_version) = find_installed_build(&library, slug, install_info)?;
+
+    status!("Fetching {slug} build manifests...");
+    let build_manifest = api::product::get_build_manifest(client, product, build_version).await?;
+    store_build_manifest(&build_manifest, &build_version.version, slug, "manifest").await?;
+    let build_manifest_chunks =
+        api::product::get_build_manifest_chunks(client, product, build_version).await?;
+    store_build_manifest(
+        &build_manifest_chunks,
+        &build_version.version,
+        slug,
+        "manifest_chunks",
+    )
+    .await?;
+    Ok(())
+}
+
+/// Finds the product and build of an installed game in the library.
+fn find_installed_build<'a>(
+    library: &'a LibraryConfig,
+    slug: &String,
+    install_info: &InstallInfo,
+) -> Result<(&'a Product, &'a ProductVersion), FreeCarnivalError> {
+    let product = match library.collection.iter().find(|p| &p.slugged_name == slug) {
+        Some(p) => p,
+        None => return Err(FreeCarnivalError::GameNotFound(slug.to_owned())),
+    };
+    match product
+        .version
+        .iter()
+        .find(|v| v.version == install_info.version && v.os == install_info.os)
+    {
+        Some(v) => Ok((product, v)),
+        None => Err(FreeCarnivalError::InstallBuild(format!(
+            "{slug} {} for {}",
+            install_info.version, install_info.os
+        ))),
+    }
+}
+
+/// Verifies an installed game's files against its build manifest. With `changed_only`, only
+/// files whose size or modification time changed since the last verification are hashed.
+/// The hashing progress bar is added to `bars`, so games verified at the same time each get
+/// their own line.
+pub(crate) async fn verify(
+    client: &reqwest::Client,
+    slug: &String,
+    install_info: &InstallInfo,
+    changed_only: bool,
+    hash_jobs: Arc<Semaphore>,
+    bars: &MultiProgress,
+) -> tokio::io::Result<VerifyResult> {
+    let mut handles: Vec<JoinHandle<Option<VerifyFailure>>> = vec![];
 
-        handles.push(tokio::spawn(async move {
-            match verify_file_hash(&file_path, &record.sha) {
-                Ok(result) => result,
-                Err(err) => {
-                    println!("Failed to verify {}: {:?}", record.file_name, err);
+    status!(
+        "Verifying build {} for {}...",
+        install_info.version,
+        install_info.os
+    );
+    let build_manifest = match read_build_manifest(&install_info.version, slug, "manifest").await {
+        Ok(build_manifest) => build_manifest,
+        // The manifest of the installed build is fetched again if it was deleted
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            fetch_installed_manifest(client, slug, install_info)
+                .await
+                .map_err(std::io::Error::other)?
+        }
+        Err(err) => return Err(err),
+    };
+    // Deferred files were never installed, so they aren't expected to be there
+    let (records, _, _) = filter_manifests(&build_manifest[..], &[], |r| {
+        !r.is_directory() && !install_info.deferred_files.contains(&r.file_name)
+    });
 
-                    false
+    // Checking sizes is cheap, so files that are obviously broken are reported before hashing
+    status!("Checking file sizes...");
+    let mut failures = vec![];
+    let mut snapshot = HashMap::new();
+    let mut changed = vec![];
+    let failure = |file_name: &str, reason: VerifyFailureReason| VerifyFailure {
+        slug: slug.to_owned(),
+        file_name: file_name.to_owned(),
+        reason,
+        expected_size: None,
+        found_size: None,
+        error: None,
+    };
+    for record in records {
+        let file_path = install_info.install_path.join(&record.file_name);
+        match tokio::fs::metadata(long_path(&file_path)).await {
+            Ok(metadata) if metadata.len() != record.size_in_bytes as u64 => {
+                failures.push(VerifyFailure {
+                    expected_size: Some(record.size_in_bytes as u64),
+                    found_size: Some(metadata.len()),
+                    ..failure(&record.file_name, VerifyFailureReason::Size)
+                });
+            }
+            Ok(metadata) => {
+                let file_snapshot = FileSnapshot {
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                };
+                let unchanged =
+                    install_info.verified_files.get(&record.file_name) == Some(&file_snapshot);
+                snapshot.insert(record.file_name.to_owned(), file_snapshot);
+                if !(changed_only && unchanged) {
+                    changed.push(record);
                 }
             }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                failures.push(failure(&record.file_name, VerifyFailureReason::Missing));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    if changed_only {
+        status!(
+            "Checking hashes of {} changed file(s), skipping {} unchanged...",
+            changed.len(),
+            snapshot.len() - changed.len()
+        );
+    } else {
+        status!("Checking file hashes...");
+    }
+    let total_bytes = changed
+        .iter()
+        .map(|record| record.size_in_bytes as u64)
+        .sum();
+    let progress = bars.add(ProgressBar::new(total_bytes));
+    progress.set_style(
+        ProgressStyle::with_template(
+            "{msg} [{percent}%] {wide_bar:.cyan/blue} {bytes:>7}/{total_bytes:7} {binary_bytes_per_sec} [{eta_precise}]",
+        )
+        .unwrap()
+        .progress_chars("##-"),
+    );
+    progress.set_message(slug.to_owned());
+    let heartbeat = {
+        let progress = progress.clone();
+        heartbeat("verify", slug, "bytes", move || {
+            (progress.position(), total_bytes)
+        })
+    };
+    for record in changed {
+        let file_path = OsPath::from(install_info.install_path.join(&record.file_name));
+        let hash_failure = failure(&record.file_name, VerifyFailureReason::Hash);
+        let permit = hash_jobs.clone().acquire_owned().await.unwrap();
+        let progress = progress.clone();
+        handles.push(tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let verified = verify_file_hash(&file_path, &record.sha);
+            progress.inc(record.size_in_bytes as u64);
+            match verified {
+                Ok(true) => None,
+                Ok(false) => Some(hash_failure),
+                Err(err) => Some(VerifyFailure {
+                    error: Some(err.to_string()),
+                    ..hash_failure
+                }),
+            }
         }));
     }
 
-    let mut result = true;
     for handle in handles {
-        if !handle.await? {
-            result = false;
-            break;
+        failures.extend(handle.await?);
+    }
+    progress.finish_and_clear();
+    if let Some(heartbeat) = heartbeat {
+        heartbeat.abort();
+    }
+
+    Ok(VerifyResult { snapshot, failures })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_install_info(os: BuildOs) -> InstallInfo {
+        InstallInfo {
+            install_path: PathBuf::from("/games/test"),
+            version: "1.0".to_owned(),
+            os,
+            tags: vec![],
+            launch_options: Default::default(),
+            winetricks_verbs: vec![],
+            last_verified: None,
+            verified_files: HashMap::new(),
+            deferred_files: vec![],
+            notified_version: None,
         }
     }
 
-    Ok(result)
+    fn test_launch_command(install_info: &InstallInfo, exe: &Path) -> LaunchCommand {
+        launch_command(
+            install_info,
+            exe,
+            #[cfg(not(target_os = "windows"))]
+            Some(Path::new("/usr/bin/wine")),
+            #[cfg(not(target_os = "windows"))]
+            Some(Path::new("/wine/prefix")),
+            None,
+            #[cfg(target_os = "linux")]
+            None,
+            #[cfg(target_os = "linux")]
+            None,
+            &["--windowed".to_owned()],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn native_builds_run_without_wine() {
+        let exe = Path::new("/games/test/game");
+        for os in [BuildOs::Linux, BuildOs::Mac] {
+            let launch_command = test_launch_command(&test_install_info(os), exe);
+
+            assert!(!launch_command.uses_wine);
+            assert!(launch_command.programs.is_empty());
+            assert!(launch_command
+                .env
+                .iter()
+                .all(|(name, _)| name != "WINEPREFIX"));
+            let command = launch_command.command();
+            assert_eq!(command.as_std().get_program(), exe);
+            let args: Vec<_> = command.as_std().get_args().collect();
+            assert_eq!(args, ["--windowed"]);
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn windows_builds_run_through_wine() {
+        let exe = Path::new("/games/test/game.exe");
+        let launch_command = test_launch_command(&test_install_info(BuildOs::Windows), exe);
+
+        assert!(launch_command.uses_wine);
+        assert_eq!(
+            launch_command.argv,
+            ["/usr/bin/wine", "/games/test/game.exe", "--windowed"]
+        );
+        assert!(launch_command
+            .env
+            .contains(&("WINEPREFIX".to_owned(), "/wine/prefix".to_owned())));
+    }
+
+    #[tokio::test]
+    async fn release_binaries_are_checked_against_their_published_sha() {
+        let asset = |name: &str, digest: Option<&str>| ReleaseAsset {
+            name: name.to_owned(),
+            browser_download_url: format!("{}/release/{name}", crate::test_server::url()),
+            digest: digest.map(str::to_owned),
+        };
+        crate::test_server::route("/release/SHA256SUMS", || {
+            (
+                200,
+                b"aaa  freecarnival-macos\nbbb *freecarnival-linux-x86_64\n".to_vec(),
+            )
+        });
+        let binary = asset("freecarnival-linux-x86_64", None);
+        let release = Release {
+            tag_name: "v1.0.0".to_owned(),
+            html_url: String::new(),
+            assets: vec![asset("SHA256SUMS", None)],
+        };
+        let client = reqwest::Client::new();
+
+        let sha = published_sha256(&client, &release, &binary).await.unwrap();
+        assert_eq!(sha.as_deref(), Some("bbb"));
+
+        let with_digest = asset("freecarnival-linux-x86_64", Some("sha256:ccc"));
+        let sha = published_sha256(&client, &release, &with_digest)
+            .await
+            .unwrap();
+        assert_eq!(sha.as_deref(), Some("ccc"));
+
+        let unpublished = Release {
+            assets: vec![],
+            ..release
+        };
+        let sha = published_sha256(&client, &unpublished, &binary)
+            .await
+            .unwrap();
+        assert_eq!(sha, None);
+    }
 }